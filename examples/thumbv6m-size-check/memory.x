@@ -0,0 +1,7 @@
+MEMORY
+{
+  /* NOTE K = KiBi = 1024 bytes */
+  /* sized like the smallest Cortex-M0 parts, e.g. the STM32F030F4 */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 16K
+  RAM : ORIGIN = 0x20000000, LENGTH = 4K
+}
@@ -0,0 +1,35 @@
+#!/usr/bin/env bash
+# Builds the firmware once per event feature (with and without `fmt`) and reports the size of the `.text` section.
+# Fails if any build exceeds its budget so that size regressions are noticed in CI.
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+SIZE_TOOL="${SIZE_TOOL:-llvm-size}"
+TARGET_DIR="target/thumbv6m-none-eabi/release"
+# upper limits for the `.text` section in bytes, deliberately set with some headroom above the current size
+TEXT_BUDGET="${TEXT_BUDGET:-2048}"
+TEXT_BUDGET_FMT="${TEXT_BUDGET_FMT:-6144}"
+
+FEATURES=(accelerometer_event button_event color_event gyro_event location_event magnetometer_event quaternion_event)
+
+failed=0
+printf "%-40s %8s\n" "features" ".text"
+for features in "${FEATURES[@]}" "${FEATURES[*]}"; do
+    for fmt in "" "fmt"; do
+        selected="${features// /,}${fmt:+,$fmt}"
+        cargo build --release --quiet --no-default-features --features "$selected"
+        text=$("$SIZE_TOOL" -A "$TARGET_DIR/thumbv6m-size-check" | awk '$1 == ".text" { print $2 }')
+        label="${selected}"
+        [ "$features" = "${FEATURES[*]}" ] && label="all events${fmt:+,$fmt}"
+        printf "%-40s %8s\n" "$label" "$text"
+        budget="$TEXT_BUDGET"
+        [ -n "$fmt" ] && budget="$TEXT_BUDGET_FMT"
+        if [ "$text" -gt "$budget" ]; then
+            echo "error: .text of $text bytes exceeds the budget of $budget bytes for features '$selected'" >&2
+            failed=1
+        fi
+    done
+done
+
+exit "$failed"
@@ -0,0 +1,44 @@
+//! Minimal firmware for a Cortex-M0 (`thumbv6m-none-eabi`) which does nothing but parse a buffer.
+//!
+//! It is not meant to be run, it only exists to measure the flash usage of the parser for the selected features,
+//! see `size-report.sh`.
+
+#![deny(unsafe_code)]
+#![forbid(unused)]
+#![no_std]
+#![no_main]
+
+use panic_halt as _;
+
+use adafruit_bluefruit_protocol::{Parser, MAX_CONTROLLER_MESSAGE_LENGTH};
+use core::hint::black_box;
+use cortex_m_rt::entry;
+
+/// Discards everything written to it, used to pull in the formatting code as a real logger would.
+#[cfg(feature = "fmt")]
+struct NullWriter;
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Write for NullWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        black_box(s);
+        Ok(())
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let buffer = [0u8; MAX_CONTROLLER_MESSAGE_LENGTH];
+
+    loop {
+        // `black_box` prevents the compiler from optimising the parser away as the input is (seemingly) unknown and the result is used.
+        for event in Parser::new(black_box(&buffer)) {
+            #[cfg(feature = "fmt")]
+            if let Err(e) = &event {
+                use core::fmt::Write;
+                let _ = write!(NullWriter, "{}", e);
+            }
+            let _ = black_box(event);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Implements the traits of the [approx crate](https://crates.io/crates/approx) for the events.
+//!
+//! Events with floats compare each value, all other events must be equal. Events of different types are never equal.
+
+#[cfg(feature = "accelerometer_event")]
+use super::accelerometer_event::AccelerometerEvent;
+#[cfg(feature = "gyro_event")]
+use super::gyro_event::GyroEvent;
+#[cfg(feature = "location_event")]
+use super::location_event::LocationEvent;
+#[cfg(feature = "magnetometer_event")]
+use super::magnetometer_event::MagnetometerEvent;
+#[cfg(feature = "quaternion_event")]
+use super::quaternion_event::QuaternionEvent;
+use super::ControllerEvent;
+use approx::{AbsDiffEq, RelativeEq, UlpsEq};
+
+/// Implements the traits for an event by comparing the values returned by the getters.
+#[allow(unused)] // not used if no event with floats has been selected
+macro_rules! impl_approx_eq {
+    ($event:ty, $($getter:ident),+) => {
+        impl AbsDiffEq for $event {
+            type Epsilon = f32;
+
+            fn default_epsilon() -> f32 {
+                f32::default_epsilon()
+            }
+
+            fn abs_diff_eq(&self, other: &Self, epsilon: f32) -> bool {
+                $(self.$getter().abs_diff_eq(&other.$getter(), epsilon))&&+
+            }
+        }
+
+        impl RelativeEq for $event {
+            fn default_max_relative() -> f32 {
+                f32::default_max_relative()
+            }
+
+            fn relative_eq(&self, other: &Self, epsilon: f32, max_relative: f32) -> bool {
+                $(self.$getter().relative_eq(&other.$getter(), epsilon, max_relative))&&+
+            }
+        }
+
+        impl UlpsEq for $event {
+            fn default_max_ulps() -> u32 {
+                f32::default_max_ulps()
+            }
+
+            fn ulps_eq(&self, other: &Self, epsilon: f32, max_ulps: u32) -> bool {
+                $(self.$getter().ulps_eq(&other.$getter(), epsilon, max_ulps))&&+
+            }
+        }
+    };
+}
+
+#[cfg(feature = "quaternion_event")]
+impl_approx_eq!(QuaternionEvent, x, y, z, w);
+#[cfg(feature = "accelerometer_event")]
+impl_approx_eq!(AccelerometerEvent, x, y, z);
+#[cfg(feature = "gyro_event")]
+impl_approx_eq!(GyroEvent, x, y, z);
+#[cfg(feature = "magnetometer_event")]
+impl_approx_eq!(MagnetometerEvent, x, y, z);
+#[cfg(feature = "location_event")]
+impl_approx_eq!(LocationEvent, latitude, longitude, altitude);
+
+/// Compares two events with `$eq` (which can access the events of the same type as `$a` and `$b`).
+macro_rules! match_events {
+    ($self:expr, $other:expr, |$a:ident, $b:ident| $eq:expr) => {
+        match ($self, $other) {
+            #[cfg(feature = "button_event")]
+            (ControllerEvent::ButtonEvent(a), ControllerEvent::ButtonEvent(b)) => a == b,
+            #[cfg(feature = "color_event")]
+            (ControllerEvent::ColorEvent(a), ControllerEvent::ColorEvent(b)) => a == b,
+            #[cfg(feature = "quaternion_event")]
+            (ControllerEvent::QuaternionEvent($a), ControllerEvent::QuaternionEvent($b)) => $eq,
+            #[cfg(feature = "accelerometer_event")]
+            (ControllerEvent::AccelerometerEvent($a), ControllerEvent::AccelerometerEvent($b)) => {
+                $eq
+            }
+            #[cfg(feature = "gyro_event")]
+            (ControllerEvent::GyroEvent($a), ControllerEvent::GyroEvent($b)) => $eq,
+            #[cfg(feature = "magnetometer_event")]
+            (ControllerEvent::MagnetometerEvent($a), ControllerEvent::MagnetometerEvent($b)) => $eq,
+            #[cfg(feature = "location_event")]
+            (ControllerEvent::LocationEvent($a), ControllerEvent::LocationEvent($b)) => $eq,
+            _ => false,
+        }
+    };
+}
+
+#[allow(unreachable_patterns, unused_variables)] // depends on the selected events
+impl AbsDiffEq for ControllerEvent {
+    type Epsilon = f32;
+
+    fn default_epsilon() -> f32 {
+        f32::default_epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: f32) -> bool {
+        match_events!(self, other, |a, b| a.abs_diff_eq(b, epsilon))
+    }
+}
+
+#[allow(unreachable_patterns, unused_variables)] // depends on the selected events
+impl RelativeEq for ControllerEvent {
+    fn default_max_relative() -> f32 {
+        f32::default_max_relative()
+    }
+
+    fn relative_eq(&self, other: &Self, epsilon: f32, max_relative: f32) -> bool {
+        match_events!(self, other, |a, b| a.relative_eq(b, epsilon, max_relative))
+    }
+}
+
+#[allow(unreachable_patterns, unused_variables)] // depends on the selected events
+impl UlpsEq for ControllerEvent {
+    fn default_max_ulps() -> u32 {
+        f32::default_max_ulps()
+    }
+
+    fn ulps_eq(&self, other: &Self, epsilon: f32, max_ulps: u32) -> bool {
+        match_events!(self, other, |a, b| a.ulps_eq(b, epsilon, max_ulps))
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "button_event",
+    feature = "gyro_event",
+    feature = "quaternion_event"
+))]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::gyro_event::GyroEvent;
+    use crate::quaternion_event::QuaternionEvent;
+    use crate::ControllerEvent;
+    use approx::{assert_abs_diff_eq, assert_relative_eq, assert_relative_ne, assert_ulps_eq};
+
+    #[test]
+    fn test_events() {
+        let computed = QuaternionEvent::new(0.6 + 0.1, 0.0, 0.0, 1.0);
+        assert_ne!(computed, QuaternionEvent::new(0.7, 0.0, 0.0, 1.0));
+        assert_relative_eq!(computed, QuaternionEvent::new(0.7, 0.0, 0.0, 1.0));
+        assert_ulps_eq!(computed, QuaternionEvent::new(0.7, 0.0, 0.0, 1.0));
+        assert_relative_ne!(computed, QuaternionEvent::new(0.7, 0.0, 0.0, 0.999));
+
+        assert_abs_diff_eq!(
+            GyroEvent::new(1.0, 2.0, 3.0),
+            GyroEvent::new(1.05, 2.0, 2.95),
+            epsilon = 0.1
+        );
+        assert_relative_ne!(
+            GyroEvent::new(1.0, 2.0, 3.0),
+            GyroEvent::new(1.0, 2.0, -3.0)
+        );
+    }
+
+    #[test]
+    fn test_controller_events() {
+        let gyro = ControllerEvent::GyroEvent(GyroEvent::new(0.6 + 0.1, 1.0, 1.0));
+        let button =
+            ControllerEvent::ButtonEvent(ButtonEvent::new(Button::Up, ButtonState::Pressed));
+
+        assert_relative_eq!(
+            gyro,
+            ControllerEvent::GyroEvent(GyroEvent::new(0.7, 1.0, 1.0))
+        );
+        assert_ulps_eq!(button, button);
+        assert_relative_ne!(
+            button,
+            ControllerEvent::ButtonEvent(ButtonEvent::new(Button::Up, ButtonState::Released))
+        );
+        assert_relative_ne!(gyro, button, epsilon = f32::MAX);
+        assert_relative_ne!(
+            gyro,
+            ControllerEvent::QuaternionEvent(QuaternionEvent::new(0.3, 1.0, 1.0, 0.0)),
+            epsilon = f32::MAX
+        );
+    }
+}
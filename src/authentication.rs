@@ -0,0 +1,556 @@
+//! Implements authenticated messages, see [`Authenticator`].
+//!
+//! This is an extension of the protocol which is not supported by the Adafruit Bluefruit LE Connect app, it can
+//! only be used with controllers which send the tag messages (see [`Signer`]).
+//!
+//! Each message is followed by a tag message `!T` which contains a counter and a [SipHash-2-4](https://en.wikipedia.org/wiki/SipHash)
+//! tag over the counter and the complete preceding message (incl. its CRC):
+//!
+//! | `!` | `T` | counter (`u32`, little endian) | tag (`u64`, little endian) | CRC |
+//! |-----|-----|--------------------------------|----------------------------|-----|
+//!
+//! The counter must increase with each message, otherwise the message is considered to be replayed. Once all counters
+//! have been used a new key is needed. Persist the counters (see [`Signer::counter`] and
+//! [`Authenticator::last_counter`]) if the controller or the receiver can restart while the key stays the same,
+//! otherwise old messages can be replayed after a restart.
+
+#[cfg(feature = "button_event")]
+use super::button_event::ButtonParseMode;
+use super::validation::Validation;
+use super::{
+    calculate_crc, check_crc, parse_command, ControllerDataPackageType, ControllerEvent,
+    ParseOptions, ProtocolParseError,
+};
+use core::cmp::min;
+#[cfg(feature = "fmt")]
+use core::error::Error;
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+use core::hash::Hasher;
+use siphasher::sip::SipHasher24;
+
+/// The key shared between the controller and the receiver.
+pub type AuthenticationKey = [u8; 16];
+
+/// Length of a tag message (`!T` + counter + tag + CRC).
+pub const TAG_MESSAGE_LENGTH: usize = 2 + 4 + 8 + 1;
+
+/// Errors which can be raised while verifying a message.
+#[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum AuthenticationError {
+    /// The message was not followed by a tag message.
+    MissingTag,
+    /// A tag message was received without a preceding message.
+    UnexpectedTag,
+    /// The tag does not match the message, i.e. it has been sent by someone not knowing the key or has been modified.
+    InvalidTag,
+    /// The counter of the tag message has already been seen. The parameter is the received counter.
+    ReplayedCounter(u32),
+    /// All counters have been used by the [`Signer`], a new key is needed.
+    CounterExhausted,
+}
+
+#[cfg(feature = "fmt")]
+impl Display for AuthenticationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        use AuthenticationError::*;
+        match self {
+            MissingTag => write!(f, "Message is not followed by a tag"),
+            UnexpectedTag => write!(f, "Tag without a preceding message"),
+            InvalidTag => write!(f, "Invalid tag"),
+            ReplayedCounter(counter) => write!(f, "Replayed counter: {}", counter),
+            CounterExhausted => write!(f, "All counters have been used"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Error for AuthenticationError {}
+
+/// Calculate the tag of a message.
+fn calculate_tag(key: &AuthenticationKey, counter: u32, message: &[u8]) -> u64 {
+    let mut hasher = SipHasher24::new_with_key(key);
+    hasher.write(&counter.to_le_bytes());
+    hasher.write(message);
+    hasher.finish()
+}
+
+/// Compare the tags in constant time, so the time taken does not reveal how many bytes of a forged tag are correct.
+fn tags_equal(expected: u64, received: &[u8]) -> bool {
+    expected
+        .to_le_bytes()
+        .iter()
+        .zip(received)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Creates the tag messages for the messages sent by a controller.
+///
+/// This is the counterpart to the [`Authenticator`], it is e.g. useful for custom controllers, simulators and tests.
+#[derive(Clone)]
+pub struct Signer {
+    key: AuthenticationKey,
+    /// The counter of the next message, `u32::MAX + 1` once all counters have been used.
+    counter: u64,
+}
+
+// implemented manually to ensure that the key never ends up in a log
+impl core::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Signer")
+            .field("counter", &self.counter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Signer {
+    /// Create a new signer. The counter starts at 0 and is incremented for each signed message.
+    pub fn new(key: AuthenticationKey) -> Self {
+        Self { key, counter: 0 }
+    }
+
+    /// Continue with the counter, e.g. with the one persisted before a restart (see [`Signer::counter`]).
+    pub fn with_counter(mut self, counter: u32) -> Self {
+        self.counter = counter.into();
+        self
+    }
+
+    /// The counter of the next message, `None` if all counters have been used.
+    pub fn counter(&self) -> Option<u32> {
+        u32::try_from(self.counter).ok()
+    }
+
+    /// Create the tag message for the message (incl. its CRC) which must be sent directly after the message.
+    ///
+    /// Returns [`AuthenticationError::CounterExhausted`] once all counters have been used, continuing with a counter
+    /// which has already been used would allow to replay the old messages.
+    pub fn sign(
+        &mut self,
+        message: &[u8],
+    ) -> Result<[u8; TAG_MESSAGE_LENGTH], AuthenticationError> {
+        let counter = self
+            .counter()
+            .ok_or(AuthenticationError::CounterExhausted)?;
+        let tag = calculate_tag(&self.key, counter, message);
+
+        let mut tag_message = [0; TAG_MESSAGE_LENGTH];
+        tag_message[0] = b'!';
+        tag_message[1] = b'T';
+        tag_message[2..6].copy_from_slice(&counter.to_le_bytes());
+        tag_message[6..14].copy_from_slice(&tag.to_le_bytes());
+        tag_message[14] = calculate_crc(&tag_message[..14]);
+
+        self.counter += 1;
+        Ok(tag_message)
+    }
+}
+
+/// Verifies the tag messages of the received messages.
+///
+/// It keeps track of the last counter (to detect replayed messages) and should thus be kept for the whole
+/// lifetime of the connection.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::authentication::{AuthenticationError, Authenticator, Signer};
+/// # use adafruit_bluefruit_protocol::ProtocolParseError;
+/// let key = *b"0123456789abcdef";
+/// let mut signer = Signer::new(key);
+/// let mut authenticator = Authenticator::new(key);
+///
+/// let message = b"!B11:";
+/// let input = [message.as_slice(), &signer.sign(message).unwrap()].concat();
+/// assert!(authenticator.parse(&input).next().unwrap().is_ok());
+///
+/// // sending the same input again is detected
+/// assert_eq!(
+///     authenticator.parse(&input).next().unwrap(),
+///     Err(ProtocolParseError::AuthenticationError(AuthenticationError::ReplayedCounter(0)))
+/// );
+///
+/// // messages without tag are rejected
+/// assert_eq!(
+///     authenticator.parse(message).next().unwrap(),
+///     Err(ProtocolParseError::AuthenticationError(AuthenticationError::MissingTag))
+/// );
+/// ```
+#[derive(Clone)]
+pub struct Authenticator {
+    key: AuthenticationKey,
+    last_counter: Option<u32>,
+    options: ParseOptions,
+}
+
+// implemented manually to ensure that the key never ends up in a log
+impl core::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Authenticator")
+            .field("last_counter", &self.last_counter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Authenticator {
+    /// Create a new authenticator which accepts any counter for the first message.
+    pub fn new(key: AuthenticationKey) -> Self {
+        Self {
+            key,
+            last_counter: None,
+            options: ParseOptions::new(),
+        }
+    }
+
+    /// Only accept messages with a counter greater than the last one, e.g. the one persisted before a restart (see
+    /// [`Authenticator::last_counter`]). `None` accepts any counter for the first message.
+    pub fn with_last_counter(mut self, last_counter: Option<u32>) -> Self {
+        self.last_counter = last_counter;
+        self
+    }
+
+    /// The counter of the last verified message, `None` if no message has been verified yet.
+    pub fn last_counter(&self) -> Option<u32> {
+        self.last_counter
+    }
+
+    /// Define how button events are parsed. By default [`ButtonParseMode::Strict`] is used.
+    #[cfg(feature = "button_event")]
+    pub fn with_button_parse_mode(mut self, mode: ButtonParseMode) -> Self {
+        self.options.button_parse_mode = mode;
+        self
+    }
+
+    /// Reject events with implausible values, see [`validation`](crate::validation). By default no validation is done.
+    pub fn with_validation(mut self, validation: Validation) -> Self {
+        self.options.validation = Some(validation);
+        self
+    }
+
+    /// Parse the input for authenticated commands, see [`AuthenticatedParser`].
+    pub fn parse<'a>(&'a mut self, input: &'a [u8]) -> AuthenticatedParser<'a> {
+        AuthenticatedParser {
+            authenticator: self,
+            input,
+            curr_pos: 0,
+        }
+    }
+
+    /// Verify the tag message for the message. The counter is only updated if the tag is valid.
+    fn verify(&mut self, message: &[u8], tag_message: &[u8]) -> Result<(), ProtocolParseError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("verifying {:a} with tag message {:a}", message, tag_message);
+
+        let len = tag_message.len();
+        if len != TAG_MESSAGE_LENGTH {
+            return Err(ProtocolParseError::InvalidLength(TAG_MESSAGE_LENGTH, len));
+        }
+        check_crc(&tag_message[..len - 1], &tag_message[len - 1])?;
+
+        let counter = u32::from_le_bytes([
+            tag_message[2],
+            tag_message[3],
+            tag_message[4],
+            tag_message[5],
+        ]);
+        if !tags_equal(
+            calculate_tag(&self.key, counter, message),
+            &tag_message[6..14],
+        ) {
+            return Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::InvalidTag,
+            ));
+        }
+        if self.last_counter.is_some_and(|last| counter <= last) {
+            return Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::ReplayedCounter(counter),
+            ));
+        }
+
+        self.last_counter = Some(counter);
+        Ok(())
+    }
+}
+
+/// A message found in the input.
+enum Message<'a> {
+    Command(ControllerDataPackageType, &'a [u8]),
+    Tag(&'a [u8]),
+}
+
+/// Parse the input for authenticated commands, created by [`Authenticator::parse`].
+///
+/// This behaves like the [`Parser`](super::Parser) but each command must be followed by a valid tag message, otherwise
+/// a [`ProtocolParseError::AuthenticationError`] is returned instead of the event.
+#[derive(Debug)]
+pub struct AuthenticatedParser<'a> {
+    authenticator: &'a mut Authenticator,
+    input: &'a [u8],
+    curr_pos: usize,
+}
+
+impl<'a> AuthenticatedParser<'a> {
+    /// Extract the next message from the input, skipping everything before its start.
+    fn next_message(&mut self) -> Option<Result<Message<'a>, ProtocolParseError>> {
+        let start = self.curr_pos
+            + self.input[self.curr_pos..]
+                .iter()
+                .position(|b| *b == b'!')?;
+        let Some(&message_type) = self.input.get(start + 1) else {
+            self.curr_pos = self.input.len();
+            return None;
+        };
+
+        let (message_len, message) = if message_type == b'T' {
+            (TAG_MESSAGE_LENGTH, None)
+        } else {
+            match ControllerDataPackageType::try_from(message_type) {
+                Ok(command) => (command.data_len() + 3, Some(command)),
+                Err(e) => {
+                    self.curr_pos = start + 1;
+                    return Some(Err(e));
+                }
+            }
+        };
+
+        let end = min(start + message_len, self.input.len());
+        self.curr_pos = end;
+        let bytes = &self.input[start..end];
+        Some(Ok(match message {
+            Some(command) => Message::Command(command, bytes),
+            None => Message::Tag(bytes),
+        }))
+    }
+}
+
+impl Iterator for AuthenticatedParser<'_> {
+    type Item = Result<ControllerEvent, ProtocolParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (command, message) = match self.next_message()? {
+            Ok(Message::Command(command, message)) => (command, message),
+            Ok(Message::Tag(_)) => {
+                return Some(Err(ProtocolParseError::AuthenticationError(
+                    AuthenticationError::UnexpectedTag,
+                )))
+            }
+            Err(e) => return Some(Err(e)),
+        };
+        let event = parse_command(command, message, &self.authenticator.options);
+
+        let pos_after_message = self.curr_pos;
+        let verified = match self.next_message() {
+            Some(Ok(Message::Tag(tag_message))) => self.authenticator.verify(message, tag_message),
+            _ => {
+                // not a tag message => handle it on the next call
+                self.curr_pos = pos_after_message;
+                Err(ProtocolParseError::AuthenticationError(
+                    AuthenticationError::MissingTag,
+                ))
+            }
+        };
+
+        Some(event.and_then(|event| verified.map(|_| event)))
+    }
+}
+
+#[cfg(all(test, feature = "button_event"))]
+mod tests {
+    use crate::authentication::{AuthenticationError, Authenticator, Signer};
+    use crate::button_event::{Button, ButtonParseError, ButtonState};
+    use crate::{ControllerEvent, ProtocolParseError};
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+
+    fn assert_is_button_event(
+        event: Option<Result<ControllerEvent, ProtocolParseError>>,
+        button: Button,
+        button_state: ButtonState,
+    ) {
+        match event {
+            Some(Ok(ControllerEvent::ButtonEvent(event))) => {
+                assert_eq!(event.button(), &button);
+                assert_eq!(event.state(), &button_state)
+            }
+            _ => panic!("expected a button event"),
+        }
+    }
+
+    #[test]
+    fn test_sign_creates_valid_message() {
+        let mut signer = Signer::new(KEY);
+        let tag_message = signer.sign(b"!B11:").unwrap();
+
+        assert_eq!(&tag_message[..6], b"!T\x00\x00\x00\x00");
+        assert!(crate::check_crc(&tag_message[..14], &tag_message[14]).is_ok());
+        assert_eq!(&signer.sign(b"!B11:").unwrap()[2..6], b"\x01\x00\x00\x00");
+    }
+
+    #[test]
+    fn test_debug_does_not_contain_key() {
+        extern crate alloc;
+        use alloc::format;
+
+        assert_eq!(
+            format!("{:?}", Signer::new(KEY)),
+            "Signer { counter: 0, .. }"
+        );
+        assert_eq!(
+            format!("{:?}", Authenticator::new(KEY)),
+            "Authenticator { last_counter: None, .. }"
+        );
+    }
+
+    #[test]
+    fn test_parse_authenticated_messages() {
+        let mut signer = Signer::new(KEY);
+        let mut authenticator = Authenticator::new(KEY);
+        let input = [
+            b"\x00!B11:".as_slice(),
+            &signer.sign(b"!B11:").unwrap(),
+            b"!B10;",
+            &signer.sign(b"!B10;").unwrap(),
+            b"\x00\x00",
+        ]
+        .concat();
+
+        let mut parser = authenticator.parse(&input);
+        assert_is_button_event(parser.next(), Button::Button1, ButtonState::Pressed);
+        assert_is_button_event(parser.next(), Button::Button1, ButtonState::Released);
+        assert_eq!(parser.next(), None);
+    }
+
+    #[test]
+    fn test_reject_wrong_key() {
+        let mut signer = Signer::new(*b"fedcba9876543210");
+        let mut authenticator = Authenticator::new(KEY);
+        let input = [b"!B11:".as_slice(), &signer.sign(b"!B11:").unwrap()].concat();
+
+        assert_eq!(
+            authenticator.parse(&input).next(),
+            Some(Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::InvalidTag
+            )))
+        );
+    }
+
+    #[test]
+    fn test_reject_modified_message() {
+        let mut signer = Signer::new(KEY);
+        let mut authenticator = Authenticator::new(KEY);
+        let input = [b"!B10;".as_slice(), &signer.sign(b"!B11:").unwrap()].concat();
+
+        assert_eq!(
+            authenticator.parse(&input).next(),
+            Some(Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::InvalidTag
+            )))
+        );
+    }
+
+    #[test]
+    fn test_reject_replayed_message() {
+        let mut signer = Signer::new(KEY);
+        let mut authenticator = Authenticator::new(KEY);
+        let first = [b"!B11:".as_slice(), &signer.sign(b"!B11:").unwrap()].concat();
+        let second = [b"!B10;".as_slice(), &signer.sign(b"!B10;").unwrap()].concat();
+
+        assert!(authenticator.parse(&first).next().unwrap().is_ok());
+        assert!(authenticator.parse(&second).next().unwrap().is_ok());
+        assert_eq!(
+            authenticator.parse(&first).next(),
+            Some(Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::ReplayedCounter(0)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_last_counter_survives_restart() {
+        let mut signer = Signer::new(KEY);
+        let mut authenticator = Authenticator::new(KEY);
+        let first = [b"!B11:".as_slice(), &signer.sign(b"!B11:").unwrap()].concat();
+        assert!(authenticator.parse(&first).next().unwrap().is_ok());
+        assert_eq!(authenticator.last_counter(), Some(0));
+
+        // both sides restart with the persisted counters
+        let mut signer = Signer::new(KEY).with_counter(signer.counter().unwrap());
+        let mut authenticator =
+            Authenticator::new(KEY).with_last_counter(authenticator.last_counter());
+        assert_eq!(
+            authenticator.parse(&first).next(),
+            Some(Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::ReplayedCounter(0)
+            )))
+        );
+        let second = [b"!B10;".as_slice(), &signer.sign(b"!B10;").unwrap()].concat();
+        assert!(authenticator.parse(&second).next().unwrap().is_ok());
+        assert_eq!(authenticator.last_counter(), Some(1));
+    }
+
+    #[test]
+    fn test_signer_does_not_wrap_around() {
+        let mut signer = Signer::new(KEY).with_counter(u32::MAX);
+        let tag_message = signer.sign(b"!B11:").unwrap();
+        assert_eq!(&tag_message[2..6], b"\xff\xff\xff\xff");
+
+        assert_eq!(signer.counter(), None);
+        assert_eq!(
+            signer.sign(b"!B11:"),
+            Err(AuthenticationError::CounterExhausted)
+        );
+    }
+
+    #[test]
+    fn test_reject_missing_tag() {
+        let mut signer = Signer::new(KEY);
+        let mut authenticator = Authenticator::new(KEY);
+        let input = [b"!B11:!B10;".as_slice(), &signer.sign(b"!B10;").unwrap()].concat();
+
+        let mut parser = authenticator.parse(&input);
+        assert_eq!(
+            parser.next(),
+            Some(Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::MissingTag
+            )))
+        );
+        assert_is_button_event(parser.next(), Button::Button1, ButtonState::Released);
+        assert_eq!(parser.next(), None);
+    }
+
+    #[test]
+    fn test_reject_unexpected_tag() {
+        let mut signer = Signer::new(KEY);
+        let mut authenticator = Authenticator::new(KEY);
+        let tag_message = signer.sign(b"!B11:").unwrap();
+
+        assert_eq!(
+            authenticator.parse(&tag_message).next(),
+            Some(Err(ProtocolParseError::AuthenticationError(
+                AuthenticationError::UnexpectedTag
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_take_precedence() {
+        let mut signer = Signer::new(KEY);
+        let mut authenticator = Authenticator::new(KEY);
+        let input = [b"!B13\x00".as_slice(), &signer.sign(b"!B13\x00").unwrap()].concat();
+
+        assert_eq!(
+            authenticator.parse(&input).next(),
+            Some(Err(ProtocolParseError::InvalidCrc(0, 0x38)))
+        );
+
+        let input = [b"!B138".as_slice(), &signer.sign(b"!B138").unwrap()].concat();
+        assert_eq!(
+            authenticator.parse(&input).next(),
+            Some(Err(ProtocolParseError::ButtonParseError(
+                ButtonParseError::UnknownButtonState(b'3')
+            )))
+        );
+    }
+}
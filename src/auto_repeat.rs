@@ -0,0 +1,199 @@
+//! Implements [`AutoRepeat`] which repeats held buttons like a keyboard, e.g. for menu navigation.
+
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::time::{Duration, Instant};
+use super::ControllerEvent;
+
+/// Generates repeated presses for held buttons.
+///
+/// Once a button has been held for the initial delay a [`ButtonState::Pressed`] event for it is returned by
+/// [`AutoRepeat::tick`] every interval until the button is released (or [`AutoRepeat::release_all`] is called, e.g.
+/// when the connection has been lost). The original press is not repeated by this, handle it as usual.
+///
+/// If `tick` is called late, missed repeats are skipped instead of being returned in a burst.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::auto_repeat::AutoRepeat;
+/// # use adafruit_bluefruit_protocol::button_event::{Button, ButtonEvent, ButtonState};
+/// # use adafruit_bluefruit_protocol::time::{Duration, Instant};
+/// let mut repeat = AutoRepeat::new()
+///     .with_delay(Duration::from_millis(400))
+///     .with_interval(Duration::from_millis(100));
+/// repeat.process(&ButtonEvent::new(Button::Down, ButtonState::Pressed), Instant::from_millis(0));
+///
+/// assert_eq!(repeat.tick(Instant::from_millis(399)), None);
+/// let event = repeat.tick(Instant::from_millis(400)).unwrap();
+/// assert_eq!(event.button(), &Button::Down);
+/// assert_eq!(repeat.tick(Instant::from_millis(450)), None);
+/// assert!(repeat.tick(Instant::from_millis(500)).is_some());
+///
+/// repeat.process(&ButtonEvent::new(Button::Down, ButtonState::Released), Instant::from_millis(550));
+/// assert_eq!(repeat.tick(Instant::from_millis(600)), None);
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AutoRepeat {
+    delay: Duration,
+    interval: Duration,
+    next_repeat: [Option<Instant>; Button::KNOWN.len()],
+}
+
+impl AutoRepeat {
+    /// Create an auto-repeat with an initial delay of 500ms and an interval of 100ms.
+    pub const fn new() -> Self {
+        Self {
+            delay: Duration::from_millis(500),
+            interval: Duration::from_millis(100),
+            next_repeat: [None; Button::KNOWN.len()],
+        }
+    }
+
+    /// Set how long a button must be held before it is repeated for the first time.
+    pub const fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set the time between two repeats. With [`Duration::ZERO`] nothing is repeated.
+    pub const fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Update the state with the event received at `now`, all other events are ignored.
+    pub fn apply(&mut self, event: &ControllerEvent, now: Instant) {
+        #[allow(irrefutable_let_patterns)] // if only `button_event` has been selected
+        if let ControllerEvent::ButtonEvent(event) = event {
+            self.process(event, now);
+        }
+    }
+
+    /// Update the state with the button event received at `now`. Events for [`Button::Other`] are ignored.
+    pub fn process(&mut self, event: &ButtonEvent, now: Instant) {
+        let Some(index) = event.button().index() else {
+            return;
+        };
+        let next_repeat = &mut self.next_repeat[index];
+        match event.state() {
+            // a repeated press of a held button does not restart the delay
+            ButtonState::Pressed if next_repeat.is_none() && self.interval != Duration::ZERO => {
+                *next_repeat = Some(now + self.delay);
+            }
+            ButtonState::Pressed => {}
+            ButtonState::Released => *next_repeat = None,
+        }
+    }
+
+    /// Returns the next repeat which is due at `now`.
+    ///
+    /// Call this regularly (e.g. every few milliseconds) while a button is held and until it returns `None`.
+    pub fn tick(&mut self, now: Instant) -> Option<ButtonEvent> {
+        let interval = self.interval;
+        Button::KNOWN
+            .iter()
+            .zip(self.next_repeat.iter_mut())
+            .find_map(|(button, next_repeat)| match next_repeat {
+                Some(at) if *at <= now => {
+                    let next = *at + interval;
+                    *next_repeat = Some(if next <= now { now + interval } else { next });
+                    Some(ButtonEvent::new(*button, ButtonState::Pressed))
+                }
+                _ => None,
+            })
+    }
+
+    /// Stop repeating all buttons, e.g. when the connection has been lost.
+    pub fn release_all(&mut self) {
+        self.next_repeat = [None; Button::KNOWN.len()];
+    }
+}
+
+impl Default for AutoRepeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auto_repeat::AutoRepeat;
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::time::{Duration, Instant};
+
+    fn process(repeat: &mut AutoRepeat, button: Button, state: ButtonState, millis: u64) {
+        repeat.process(
+            &ButtonEvent::new(button, state),
+            Instant::from_millis(millis),
+        );
+    }
+
+    fn tick(repeat: &mut AutoRepeat, millis: u64) -> Option<Button> {
+        repeat
+            .tick(Instant::from_millis(millis))
+            .map(|event| *event.button())
+    }
+
+    #[test]
+    fn test_repeats_until_released() {
+        let mut repeat = AutoRepeat::new();
+        process(&mut repeat, Button::Up, ButtonState::Pressed, 0);
+
+        assert_eq!(tick(&mut repeat, 499), None);
+        assert_eq!(tick(&mut repeat, 500), Some(Button::Up));
+        assert_eq!(tick(&mut repeat, 500), None);
+        assert_eq!(tick(&mut repeat, 600), Some(Button::Up));
+        assert_eq!(tick(&mut repeat, 700), Some(Button::Up));
+
+        process(&mut repeat, Button::Up, ButtonState::Released, 750);
+        assert_eq!(tick(&mut repeat, 800), None);
+    }
+
+    #[test]
+    fn test_late_tick_skips_repeats() {
+        let mut repeat = AutoRepeat::new();
+        process(&mut repeat, Button::Up, ButtonState::Pressed, 0);
+
+        assert_eq!(tick(&mut repeat, 1000), Some(Button::Up));
+        assert_eq!(tick(&mut repeat, 1000), None);
+        assert_eq!(tick(&mut repeat, 1099), None);
+        assert_eq!(tick(&mut repeat, 1100), Some(Button::Up));
+    }
+
+    #[test]
+    fn test_duplicate_press_does_not_restart_delay() {
+        let mut repeat = AutoRepeat::new();
+        process(&mut repeat, Button::Left, ButtonState::Pressed, 0);
+        process(&mut repeat, Button::Left, ButtonState::Pressed, 300);
+
+        assert_eq!(tick(&mut repeat, 500), Some(Button::Left));
+    }
+
+    #[test]
+    fn test_several_buttons() {
+        let mut repeat = AutoRepeat::new();
+        process(&mut repeat, Button::Left, ButtonState::Pressed, 0);
+        process(&mut repeat, Button::Button2, ButtonState::Pressed, 0);
+
+        assert_eq!(tick(&mut repeat, 500), Some(Button::Button2));
+        assert_eq!(tick(&mut repeat, 500), Some(Button::Left));
+        assert_eq!(tick(&mut repeat, 500), None);
+    }
+
+    #[test]
+    fn test_release_all() {
+        let mut repeat = AutoRepeat::new();
+        process(&mut repeat, Button::Left, ButtonState::Pressed, 0);
+        repeat.release_all();
+
+        assert_eq!(tick(&mut repeat, 1000), None);
+    }
+
+    #[test]
+    fn test_zero_interval_disables_repeat() {
+        let mut repeat = AutoRepeat::new().with_interval(Duration::ZERO);
+        process(&mut repeat, Button::Left, ButtonState::Pressed, 0);
+
+        assert_eq!(tick(&mut repeat, 1000), None);
+    }
+}
@@ -0,0 +1,233 @@
+//! Implements [`Bindings`], a table which maps button events and chords to the actions of your application.
+
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::button_states::{ButtonSet, ButtonStates};
+use super::ControllerEvent;
+
+/// What triggers a [`Binding`].
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Trigger {
+    /// The button changed to the state.
+    Button(Button, ButtonState),
+    /// The press of a button which completes the chord, i.e. all buttons of the set are held afterwards.
+    Chord(ButtonSet),
+}
+
+/// Maps a [`Trigger`] to an action.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Binding<A> {
+    trigger: Trigger,
+    action: A,
+}
+
+impl<A> Binding<A> {
+    /// Create a binding which is triggered when the button changes to the state.
+    pub const fn new(button: Button, state: ButtonState, action: A) -> Self {
+        Self {
+            trigger: Trigger::Button(button, state),
+            action,
+        }
+    }
+
+    /// Create a binding which is triggered when the button is pressed.
+    pub const fn pressed(button: Button, action: A) -> Self {
+        Self::new(button, ButtonState::Pressed, action)
+    }
+
+    /// Create a binding which is triggered when the button is released.
+    pub const fn released(button: Button, action: A) -> Self {
+        Self::new(button, ButtonState::Released, action)
+    }
+
+    /// Create a binding which is triggered when all buttons of the chord are held.
+    pub const fn chord(buttons: ButtonSet, action: A) -> Self {
+        Self {
+            trigger: Trigger::Chord(buttons),
+            action,
+        }
+    }
+
+    /// What triggers the binding.
+    pub const fn trigger(&self) -> &Trigger {
+        &self.trigger
+    }
+
+    /// The action of the binding.
+    pub const fn action(&self) -> &A {
+        &self.action
+    }
+}
+
+/// A table of [`Binding`]s which resolves button events to actions.
+///
+/// The table is usually defined as a `const`, so several control layouts can be kept in flash and swapped at runtime
+/// by just using another table. The bindings are checked in the order of the table and the first match wins, so list
+/// chords before the bindings of their buttons.
+///
+/// Chords can only be resolved with [`Bindings::resolve_with`] which needs the [`ButtonStates`] to know which buttons
+/// are held, [`Bindings::resolve`] only resolves the bindings of single buttons.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::bindings::{Binding, Bindings};
+/// # use adafruit_bluefruit_protocol::button_event::Button;
+/// # use adafruit_bluefruit_protocol::Parser;
+/// #[derive(PartialEq, Debug, Copy, Clone)]
+/// enum Action {
+///     Forward,
+///     Stop,
+///     Grab,
+///     SwitchLayout,
+/// }
+///
+/// const DRIVE: Bindings<Action> = Bindings::new(&[
+///     Binding::pressed(Button::Up, Action::Forward),
+///     Binding::released(Button::Up, Action::Stop),
+///     Binding::pressed(Button::Button4, Action::SwitchLayout),
+/// ]);
+/// const ARM: Bindings<Action> = Bindings::new(&[
+///     Binding::pressed(Button::Button1, Action::Grab),
+///     Binding::pressed(Button::Button4, Action::SwitchLayout),
+/// ]);
+///
+/// let mut layout = &DRIVE;
+/// let mut actions = Parser::new(b"!B516!B11:!B417!B11:").filter_map(|event| {
+///     let action = layout.resolve(&event.unwrap());
+///     if action == Some(Action::SwitchLayout) {
+///         layout = &ARM;
+///     }
+///     action
+/// });
+/// assert_eq!(actions.next(), Some(Action::Forward));
+/// assert_eq!(actions.next(), Some(Action::SwitchLayout));
+/// assert_eq!(actions.next(), Some(Action::Grab));
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Bindings<A: 'static> {
+    bindings: &'static [Binding<A>],
+}
+
+impl<A: Copy> Bindings<A> {
+    /// Create the table from the bindings.
+    pub const fn new(bindings: &'static [Binding<A>]) -> Self {
+        Self { bindings }
+    }
+
+    /// All bindings of the table.
+    pub const fn bindings(&self) -> &'static [Binding<A>] {
+        self.bindings
+    }
+
+    /// The action bound to the event, `None` for other events and events without a binding. Chords are ignored.
+    pub fn resolve(&self, event: &ControllerEvent) -> Option<A> {
+        self.resolve_event(event, None)
+    }
+
+    /// The action bound to the event including chords, the states must already have been updated with the event.
+    pub fn resolve_with(&self, event: &ControllerEvent, states: &ButtonStates) -> Option<A> {
+        self.resolve_event(event, Some(states.pressed()))
+    }
+
+    /// The action bound to the button event. Chords are ignored.
+    pub fn resolve_button(&self, event: &ButtonEvent) -> Option<A> {
+        self.find(event, None)
+    }
+
+    fn resolve_event(&self, event: &ControllerEvent, held: Option<ButtonSet>) -> Option<A> {
+        #[allow(irrefutable_let_patterns)] // if only `button_event` has been selected
+        if let ControllerEvent::ButtonEvent(event) = event {
+            self.find(event, held)
+        } else {
+            None
+        }
+    }
+
+    fn find(&self, event: &ButtonEvent, held: Option<ButtonSet>) -> Option<A> {
+        let (button, state) = (*event.button(), *event.state());
+        self.bindings
+            .iter()
+            .find(|binding| match binding.trigger {
+                Trigger::Button(b, s) => b == button && s == state,
+                Trigger::Chord(chord) => {
+                    state == ButtonState::Pressed
+                        && chord.contains(button)
+                        && held.is_some_and(|held| held.contains_all(chord))
+                }
+            })
+            .map(|binding| binding.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::{Binding, Bindings};
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::button_states::{ButtonSet, ButtonStates};
+    use crate::ControllerEvent;
+
+    const BINDINGS: Bindings<u8> = Bindings::new(&[
+        Binding::chord(
+            ButtonSet::new().with(Button::Button1).with(Button::Button2),
+            12,
+        ),
+        Binding::pressed(Button::Button1, 1),
+        Binding::released(Button::Button1, 10),
+        Binding::new(Button::Button2, ButtonState::Pressed, 2),
+        Binding::pressed(Button::Button1, 99),
+    ]);
+
+    fn event(button: Button, state: ButtonState) -> ControllerEvent {
+        ControllerEvent::ButtonEvent(ButtonEvent::new(button, state))
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            BINDINGS.resolve(&event(Button::Button1, ButtonState::Pressed)),
+            Some(1)
+        );
+        assert_eq!(
+            BINDINGS.resolve(&event(Button::Button1, ButtonState::Released)),
+            Some(10)
+        );
+        assert_eq!(
+            BINDINGS.resolve(&event(Button::Button2, ButtonState::Released)),
+            None
+        );
+        assert_eq!(
+            BINDINGS.resolve_button(&ButtonEvent::new(Button::Up, ButtonState::Pressed)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resolve_chord() {
+        let mut states = ButtonStates::new();
+        let mut resolve = |button, state| {
+            let event = event(button, state);
+            states.apply(&event);
+            BINDINGS.resolve_with(&event, &states)
+        };
+
+        assert_eq!(resolve(Button::Button2, ButtonState::Pressed), Some(2));
+        assert_eq!(resolve(Button::Button1, ButtonState::Pressed), Some(12));
+        assert_eq!(resolve(Button::Button2, ButtonState::Released), None);
+        assert_eq!(resolve(Button::Button1, ButtonState::Released), Some(10));
+        assert_eq!(resolve(Button::Button1, ButtonState::Pressed), Some(1));
+    }
+
+    #[test]
+    fn test_empty_bindings() {
+        let bindings: Bindings<u8> = Bindings::new(&[]);
+        assert_eq!(
+            bindings.resolve(&event(Button::Button1, ButtonState::Pressed)),
+            None
+        );
+        assert_eq!(BINDINGS.bindings().len(), 5);
+    }
+}
@@ -1,12 +1,18 @@
 //! Implements the [`ButtonEvent`] and its parsing from the protocol.
 
-use super::ProtocolParseError;
+use super::text::{TextParseError, Tokens};
+use super::{ControllerDataPackageType, ProtocolParseError};
+#[cfg(feature = "fmt")]
 use core::error::Error;
+#[cfg(feature = "fmt")]
 use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 
 /// Errors which can be raised while parsing a button event.
 #[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum ButtonParseError {
     /// The message contained an unknown button. For the known buttons see [`Button`].
     UnknownButton(u8),
@@ -14,6 +20,7 @@ pub enum ButtonParseError {
     UnknownButtonState(u8),
 }
 
+#[cfg(feature = "fmt")]
 impl Display for ButtonParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         use ButtonParseError::*;
@@ -24,11 +31,29 @@ impl Display for ButtonParseError {
     }
 }
 
+#[cfg(feature = "fmt")]
 impl Error for ButtonParseError {}
 
+/// Defines how strictly button events are parsed.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum ButtonParseMode {
+    /// Only the buttons & states sent by the control pad of the Adafruit app are accepted,
+    /// everything else results in a [`ButtonParseError`].
+    #[default]
+    Strict,
+    /// Unknown buttons are returned as [`Button::Other`] and any state other than `'0'` is considered to be [`ButtonState::Pressed`].
+    /// Use this for app versions or custom control pads which send more buttons.
+    Lenient,
+}
+
 /// Lists all possible buttons which can be sent in the event.
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub enum Button {
     Button1,
@@ -39,9 +64,28 @@ pub enum Button {
     Down,
     Left,
     Right,
+    /// A button which is not sent by the Adafruit app, the parameter is the raw ID from the protocol.
+    /// This is only returned when parsing with [`ButtonParseMode::Lenient`].
+    ///
+    /// It should only hold IDs which are not used by the other buttons, use [`Button::from_id_lenient`] to create it.
+    /// Otherwise it is sent and tracked (e.g. in a [`ButtonSet`](crate::button_states::ButtonSet)) like the matching
+    /// button, but does not compare equal to it.
+    Other(u8),
 }
 
 impl Button {
+    /// All buttons sent by the control pad of the Adafruit app.
+    pub(crate) const KNOWN: [Button; 8] = [
+        Button::Button1,
+        Button::Button2,
+        Button::Button3,
+        Button::Button4,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
     /// Maps the ID in the protocol to the [`Button`].
     pub fn from_id(input: &u8) -> Result<Button, ButtonParseError> {
         match input {
@@ -56,12 +100,94 @@ impl Button {
             _ => Err(ButtonParseError::UnknownButton(*input)),
         }
     }
+
+    /// Maps the ID in the protocol to the [`Button`], unknown IDs are returned as [`Button::Other`].
+    pub fn from_id_lenient(input: &u8) -> Button {
+        Button::from_id(input).unwrap_or(Button::Other(*input))
+    }
+
+    /// The ID of the button in the protocol.
+    pub fn id(&self) -> u8 {
+        match self {
+            Button::Button1 => b'1',
+            Button::Button2 => b'2',
+            Button::Button3 => b'3',
+            Button::Button4 => b'4',
+            Button::Up => b'5',
+            Button::Down => b'6',
+            Button::Left => b'7',
+            Button::Right => b'8',
+            Button::Other(id) => *id,
+        }
+    }
+
+    /// The index of the button in [`Button::KNOWN`], `None` for a [`Button::Other`] with an unknown ID.
+    pub(crate) const fn index(&self) -> Option<usize> {
+        match self {
+            Button::Button1 => Some(0),
+            Button::Button2 => Some(1),
+            Button::Button3 => Some(2),
+            Button::Button4 => Some(3),
+            Button::Up => Some(4),
+            Button::Down => Some(5),
+            Button::Left => Some(6),
+            Button::Right => Some(7),
+            // it is sent as the known button with the same ID, so it is treated the same
+            Button::Other(id @ b'1'..=b'8') => Some((*id - b'1') as usize),
+            Button::Other(_) => None,
+        }
+    }
+
+    /// Maps the name in the text form to the [`Button`], see [`text`](super::text).
+    fn from_name(name: &str) -> Option<Button> {
+        match name {
+            "1" => Some(Button::Button1),
+            "2" => Some(Button::Button2),
+            "3" => Some(Button::Button3),
+            "4" => Some(Button::Button4),
+            "up" => Some(Button::Up),
+            "down" => Some(Button::Down),
+            "left" => Some(Button::Left),
+            "right" => Some(Button::Right),
+            _ => u8::from_str_radix(name.strip_prefix("0x")?, 16)
+                .ok()
+                .map(|id| Button::from_id_lenient(&id)),
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Button {
+    /// Any button, [`Button::Other`] is only generated for IDs which are not used by the other buttons.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Button::from_id_lenient(&u8::arbitrary(u)?))
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for Button {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        use Button::*;
+        match self {
+            Button1 => write!(f, "1"),
+            Button2 => write!(f, "2"),
+            Button3 => write!(f, "3"),
+            Button4 => write!(f, "4"),
+            Up => write!(f, "up"),
+            Down => write!(f, "down"),
+            Left => write!(f, "left"),
+            Right => write!(f, "right"),
+            Other(id) => write!(f, "{:#04x}", id),
+        }
+    }
 }
 
 /// The state of the button.
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub enum ButtonState {
     Released,
@@ -77,11 +203,50 @@ impl ButtonState {
             _ => Err(ButtonParseError::UnknownButtonState(*input)),
         }
     }
+
+    /// Maps the ID in the protocol to the [`ButtonState`], any ID other than `'0'` is considered to be [`ButtonState::Pressed`].
+    pub fn from_id_lenient(input: &u8) -> ButtonState {
+        match input {
+            b'0' => ButtonState::Released,
+            _ => ButtonState::Pressed,
+        }
+    }
+
+    /// The ID of the button state in the protocol.
+    pub fn id(&self) -> u8 {
+        match self {
+            ButtonState::Released => b'0',
+            ButtonState::Pressed => b'1',
+        }
+    }
+
+    /// Maps the name in the text form to the [`ButtonState`], see [`text`](super::text).
+    fn from_name(name: &str) -> Option<ButtonState> {
+        match name {
+            "released" => Some(ButtonState::Released),
+            "pressed" => Some(ButtonState::Pressed),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for ButtonState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        use ButtonState::*;
+        match self {
+            Released => write!(f, "released"),
+            Pressed => write!(f, "pressed"),
+        }
+    }
 }
 
 /// Represents a button event from the protocol.
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub struct ButtonEvent {
     button: Button,
@@ -95,21 +260,73 @@ impl TryFrom<&[u8]> for ButtonEvent {
     ///
     /// The full command is not validated here, identifying the command as a button command and CRC validation is the responsibility of the caller!
     fn try_from(input: &[u8]) -> Result<Self, Self::Error> {
+        ButtonEvent::parse(input, ButtonParseMode::Strict)
+    }
+}
+
+impl FromStr for ButtonEvent {
+    type Err = TextParseError;
+
+    /// Parse the text form of the event (e.g. `button 1 pressed`), see [`text`](super::text).
+    ///
+    /// Buttons which are not sent by the Adafruit app are given as hex ID (e.g. `button 0x39 pressed`) and result in
+    /// [`Button::Other`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Tokens::new(s);
+        tokens.expect_event(ControllerDataPackageType::ButtonCommand)?;
+        let button = Button::from_name(tokens.next()?).ok_or(tokens.invalid())?;
+        let state = ButtonState::from_name(tokens.next()?).ok_or(tokens.invalid())?;
+        tokens.end()?;
+        Ok(ButtonEvent { button, state })
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for ButtonEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "button {} {}", self.button, self.state)
+    }
+}
+
+#[allow(missing_docs)] // the names are already obvious enough
+impl ButtonEvent {
+    /// Create the event, e.g. `ButtonEvent::new(Button::Up, ButtonState::Pressed)`.
+    pub const fn new(button: Button, state: ButtonState) -> Self {
+        Self { button, state }
+    }
+
+    pub const fn with_button(mut self, button: Button) -> Self {
+        self.button = button;
+        self
+    }
+
+    pub const fn with_state(mut self, state: ButtonState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Parse the data section of a button command using the given [`ButtonParseMode`].
+    ///
+    /// The full command is not validated here, identifying the command as a button command and CRC validation is the responsibility of the caller!
+    pub fn parse(input: &[u8], mode: ButtonParseMode) -> Result<Self, ProtocolParseError> {
         let expected_len = 2;
         if input.len() != expected_len {
-            Err(ProtocolParseError::InvalidLength(expected_len, input.len()))
-        } else {
-            Ok(ButtonEvent {
+            return Err(ProtocolParseError::InvalidLength(expected_len, input.len()));
+        }
+
+        match mode {
+            ButtonParseMode::Strict => Ok(ButtonEvent {
                 button: Button::from_id(&input[0]).map_err(ProtocolParseError::ButtonParseError)?,
                 state: ButtonState::from_id(&input[1])
                     .map_err(ProtocolParseError::ButtonParseError)?,
-            })
+            }),
+            ButtonParseMode::Lenient => Ok(ButtonEvent {
+                button: Button::from_id_lenient(&input[0]),
+                state: ButtonState::from_id_lenient(&input[1]),
+            }),
         }
     }
-}
 
-#[allow(missing_docs)] // the names are already obvious enough
-impl ButtonEvent {
     pub fn button(&self) -> &Button {
         &self.button
     }
@@ -120,7 +337,9 @@ impl ButtonEvent {
 
 #[cfg(test)]
 mod tests {
-    use crate::button_event::{Button, ButtonEvent, ButtonParseError, ButtonState};
+    use crate::button_event::{
+        Button, ButtonEvent, ButtonParseError, ButtonParseMode, ButtonState,
+    };
     use crate::ProtocolParseError;
 
     fn assert_is_button_event(
@@ -133,7 +352,7 @@ mod tests {
                 assert_eq!(event.button(), &button);
                 assert_eq!(event.state(), &button_state)
             }
-            _ => assert!(false),
+            _ => panic!("expected a button event"),
         }
     }
 
@@ -248,4 +467,135 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn test_parse_lenient_unknown_button() {
+        let input: &[u8] = b"91";
+        assert_is_button_event(
+            &ButtonEvent::parse(input, ButtonParseMode::Lenient),
+            Button::Other(b'9'),
+            ButtonState::Pressed,
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient_known_button() {
+        let input: &[u8] = b"50";
+        assert_is_button_event(
+            &ButtonEvent::parse(input, ButtonParseMode::Lenient),
+            Button::Up,
+            ButtonState::Released,
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient_button_state() {
+        let input: &[u8] = b"13";
+        assert_is_button_event(
+            &ButtonEvent::parse(input, ButtonParseMode::Lenient),
+            Button::Button1,
+            ButtonState::Pressed,
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient_invalid_length() {
+        let input: &[u8] = b"123";
+        assert_eq!(
+            ButtonEvent::parse(input, ButtonParseMode::Lenient),
+            Err(ProtocolParseError::InvalidLength(2, 3))
+        );
+    }
+
+    #[test]
+    fn test_text_form() {
+        let cases = [
+            ("button 1 pressed", Button::Button1, ButtonState::Pressed),
+            ("button 4 released", Button::Button4, ButtonState::Released),
+            ("button up pressed", Button::Up, ButtonState::Pressed),
+            (
+                "button right released",
+                Button::Right,
+                ButtonState::Released,
+            ),
+            (
+                "button 0x39 pressed",
+                Button::Other(b'9'),
+                ButtonState::Pressed,
+            ),
+        ];
+
+        for (text, button, state) in cases {
+            let event: ButtonEvent = text.parse().unwrap();
+            assert_eq!(event, ButtonEvent { button, state });
+            #[cfg(feature = "fmt")]
+            {
+                extern crate alloc;
+                use alloc::string::ToString;
+                assert_eq!(event.to_string(), text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_form_known_button_as_id() {
+        assert_eq!(
+            "button 0x35 pressed".parse(),
+            Ok(ButtonEvent {
+                button: Button::Up,
+                state: ButtonState::Pressed
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_text_form() {
+        use crate::text::TextParseError;
+
+        assert_eq!(
+            "button 9 pressed".parse::<ButtonEvent>(),
+            Err(TextParseError::InvalidValue(1))
+        );
+        assert_eq!(
+            "button 1 down".parse::<ButtonEvent>(),
+            Err(TextParseError::InvalidValue(2))
+        );
+        assert_eq!(
+            "button 1 pressed now".parse::<ButtonEvent>(),
+            Err(TextParseError::TrailingInput)
+        );
+    }
+
+    #[test]
+    fn test_ids_round_trip() {
+        for id in b'1'..=b'8' {
+            assert_eq!(Button::from_id(&id).unwrap().id(), id);
+        }
+        assert_eq!(Button::Other(b'9').id(), b'9');
+        for id in [b'0', b'1'] {
+            assert_eq!(ButtonState::from_id(&id).unwrap().id(), id);
+        }
+    }
+
+    #[test]
+    fn test_index_of_other_button() {
+        for (index, button) in Button::KNOWN.iter().enumerate() {
+            assert_eq!(button.index(), Some(index));
+            assert_eq!(Button::Other(button.id()).index(), Some(index));
+        }
+        assert_eq!(Button::Other(b'9').index(), None);
+        assert_eq!(Button::from_id_lenient(&b'1'), Button::Button1);
+    }
+
+    #[test]
+    fn test_new() {
+        let event = ButtonEvent::new(Button::Up, ButtonState::Pressed);
+        assert_eq!(event.button(), &Button::Up);
+        assert_eq!(event.state(), &ButtonState::Pressed);
+
+        let event = event
+            .with_state(ButtonState::Released)
+            .with_button(Button::Left);
+        assert_eq!(event, ButtonEvent::try_from(b"70".as_slice()).unwrap());
+    }
 }
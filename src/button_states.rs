@@ -0,0 +1,343 @@
+//! Implements [`ButtonStates`] which tracks which buttons are held and which have just been pressed or released.
+
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::{ControllerEvent, ProtocolParseError};
+
+/// A set of buttons. [`Button::Other`] can not be part of the set, it is ignored.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::button_event::Button;
+/// # use adafruit_bluefruit_protocol::button_states::ButtonSet;
+/// const ARM: ButtonSet = ButtonSet::new().with(Button::Button1).with(Button::Button2);
+/// assert!(ARM.contains(Button::Button2));
+/// assert!(ARM.iter().eq([Button::Button1, Button::Button2]));
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ButtonSet {
+    bits: u8,
+}
+
+impl ButtonSet {
+    /// Create an empty set.
+    pub const fn new() -> Self {
+        Self { bits: 0 }
+    }
+
+    /// Add the button to the set.
+    pub const fn with(mut self, button: Button) -> Self {
+        self.bits |= bit(&button);
+        self
+    }
+
+    /// Add the button to the set.
+    pub fn insert(&mut self, button: Button) {
+        self.bits |= bit(&button);
+    }
+
+    /// Remove the button from the set.
+    pub fn remove(&mut self, button: Button) {
+        self.bits &= !bit(&button);
+    }
+
+    /// Whether the button is part of the set.
+    pub const fn contains(&self, button: Button) -> bool {
+        let bit = bit(&button);
+        bit != 0 && self.bits & bit != 0
+    }
+
+    /// Whether all buttons of the other set are part of this set.
+    pub const fn contains_all(&self, other: ButtonSet) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// The number of buttons in the set.
+    pub const fn len(&self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    /// Whether the set is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// All buttons of the set (in the order of [`Button`]).
+    pub fn iter(&self) -> ButtonSetIter {
+        ButtonSetIter { bits: self.bits }
+    }
+}
+
+impl FromIterator<Button> for ButtonSet {
+    fn from_iter<T: IntoIterator<Item = Button>>(iter: T) -> Self {
+        let mut set = ButtonSet::new();
+        for button in iter {
+            set.insert(button);
+        }
+        set
+    }
+}
+
+impl IntoIterator for ButtonSet {
+    type Item = Button;
+    type IntoIter = ButtonSetIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the buttons of a [`ButtonSet`].
+#[derive(Debug, Clone)]
+pub struct ButtonSetIter {
+    bits: u8,
+}
+
+impl Iterator for ButtonSetIter {
+    type Item = Button;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bits == 0 {
+            return None;
+        }
+        let index = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some(Button::KNOWN[index])
+    }
+}
+
+/// The bit of the button in a [`ButtonSet`], `0` for [`Button::Other`].
+const fn bit(button: &Button) -> u8 {
+    match button.index() {
+        Some(index) => 1 << index,
+        None => 0,
+    }
+}
+
+/// Tracks the state of all buttons, updated from the [`ButtonEvent`]s.
+///
+/// Besides the current state the edges (buttons which have been pressed or released) are recorded until the next
+/// call to [`ButtonStates::update`], which is usually done once per iteration of the main or control loop.
+/// A button which is pressed and released within the same iteration is reported as both just pressed and just
+/// released, so short taps are not lost.
+///
+/// Repeated events are ignored: a press of a button which is already held (or a release of a button which is not held)
+/// does not result in an edge. Events for [`Button::Other`] are ignored as well.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::button_event::Button;
+/// # use adafruit_bluefruit_protocol::button_states::ButtonStates;
+/// # use adafruit_bluefruit_protocol::Parser;
+/// let mut buttons = ButtonStates::new();
+/// buttons.apply_all(Parser::new(b"!B11:!B516"));
+/// assert!(buttons.is_pressed(Button::Button1));
+/// assert!(buttons.just_pressed(Button::Up));
+/// assert!(buttons.pressed_iter().eq([Button::Button1, Button::Up]));
+///
+/// buttons.update();
+/// buttons.apply_all(Parser::new(b"!B10;"));
+/// assert!(buttons.just_released(Button::Button1));
+/// assert!(!buttons.just_pressed(Button::Up));
+/// assert!(buttons.pressed_iter().eq([Button::Up]));
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ButtonStates {
+    pressed: ButtonSet,
+    just_pressed: ButtonSet,
+    just_released: ButtonSet,
+}
+
+impl ButtonStates {
+    /// Create the states with all buttons released.
+    pub const fn new() -> Self {
+        Self {
+            pressed: ButtonSet::new(),
+            just_pressed: ButtonSet::new(),
+            just_released: ButtonSet::new(),
+        }
+    }
+
+    /// Update the state with the event, all other events are ignored.
+    pub fn apply(&mut self, event: &ControllerEvent) {
+        #[allow(irrefutable_let_patterns)] // if only `button_event` has been selected
+        if let ControllerEvent::ButtonEvent(event) = event {
+            self.apply_button(event);
+        }
+    }
+
+    /// Update the state with all events from the parser. Errors are skipped, their number is returned.
+    pub fn apply_all<I>(&mut self, events: I) -> usize
+    where
+        I: IntoIterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+    {
+        let mut errors = 0;
+        for event in events {
+            match event {
+                Ok(event) => self.apply(&event),
+                Err(_) => errors += 1,
+            }
+        }
+        errors
+    }
+
+    /// Update the state with the button event.
+    pub fn apply_button(&mut self, event: &ButtonEvent) {
+        let button = *event.button();
+        match event.state() {
+            ButtonState::Pressed if !self.pressed.contains(button) => {
+                self.pressed.insert(button);
+                self.just_pressed.insert(button);
+            }
+            ButtonState::Released if self.pressed.contains(button) => {
+                self.pressed.remove(button);
+                self.just_released.insert(button);
+            }
+            _ => {}
+        }
+    }
+
+    /// Release all buttons, e.g. when the connection has been lost. They are reported as just released.
+    pub fn release_all(&mut self) {
+        self.just_released.bits |= self.pressed.bits;
+        self.pressed = ButtonSet::new();
+    }
+
+    /// Clear the edges, call this once per iteration after handling them.
+    pub fn update(&mut self) {
+        self.just_pressed = ButtonSet::new();
+        self.just_released = ButtonSet::new();
+    }
+
+    /// Whether the button is currently held.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed.contains(button)
+    }
+
+    /// Whether the button has been pressed since the last [`ButtonStates::update`].
+    pub fn just_pressed(&self, button: Button) -> bool {
+        self.just_pressed.contains(button)
+    }
+
+    /// Whether the button has been released since the last [`ButtonStates::update`].
+    pub fn just_released(&self, button: Button) -> bool {
+        self.just_released.contains(button)
+    }
+
+    /// All buttons which are currently held.
+    pub fn pressed(&self) -> ButtonSet {
+        self.pressed
+    }
+
+    /// Iterate over all buttons which are currently held.
+    pub fn pressed_iter(&self) -> ButtonSetIter {
+        self.pressed.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::button_states::{ButtonSet, ButtonStates};
+
+    fn press(states: &mut ButtonStates, button: Button) {
+        states.apply_button(&ButtonEvent::new(button, ButtonState::Pressed));
+    }
+
+    fn release(states: &mut ButtonStates, button: Button) {
+        states.apply_button(&ButtonEvent::new(button, ButtonState::Released));
+    }
+
+    #[test]
+    fn test_edges_are_cleared_by_update() {
+        let mut states = ButtonStates::new();
+        press(&mut states, Button::Left);
+        assert!(states.is_pressed(Button::Left));
+        assert!(states.just_pressed(Button::Left));
+
+        states.update();
+        assert!(states.is_pressed(Button::Left));
+        assert!(!states.just_pressed(Button::Left));
+
+        release(&mut states, Button::Left);
+        assert!(!states.is_pressed(Button::Left));
+        assert!(states.just_released(Button::Left));
+        states.update();
+        assert_eq!(states, ButtonStates::new());
+    }
+
+    #[test]
+    fn test_tap_within_one_update() {
+        let mut states = ButtonStates::new();
+        press(&mut states, Button::Button2);
+        release(&mut states, Button::Button2);
+
+        assert!(!states.is_pressed(Button::Button2));
+        assert!(states.just_pressed(Button::Button2));
+        assert!(states.just_released(Button::Button2));
+    }
+
+    #[test]
+    fn test_duplicate_events() {
+        let mut states = ButtonStates::new();
+        release(&mut states, Button::Button3);
+        assert!(!states.just_released(Button::Button3));
+
+        press(&mut states, Button::Button3);
+        states.update();
+        press(&mut states, Button::Button3);
+        assert!(states.is_pressed(Button::Button3));
+        assert!(!states.just_pressed(Button::Button3));
+    }
+
+    #[test]
+    fn test_other_buttons_are_ignored() {
+        let mut states = ButtonStates::new();
+        press(&mut states, Button::Other(b'9'));
+
+        assert_eq!(states, ButtonStates::new());
+        assert!(!states.is_pressed(Button::Other(b'9')));
+    }
+
+    #[test]
+    fn test_release_all() {
+        let mut states = ButtonStates::new();
+        press(&mut states, Button::Right);
+        press(&mut states, Button::Button4);
+        assert!(states.pressed_iter().eq([Button::Button4, Button::Right]));
+
+        states.release_all();
+        assert_eq!(states.pressed_iter().count(), 0);
+        assert!(states.just_released(Button::Right));
+        assert!(states.just_released(Button::Button4));
+        assert!(!states.just_released(Button::Up));
+    }
+
+    #[test]
+    fn test_button_set() {
+        let mut set: ButtonSet = [Button::Down, Button::Button1, Button::Other(b'9')]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(Button::Down));
+        assert!(!set.contains(Button::Other(b'9')));
+        assert!(set.contains(Button::Other(b'1')));
+        assert_eq!(
+            set,
+            ButtonSet::new().with(Button::Button1).with(Button::Down)
+        );
+
+        assert!(set.contains_all(ButtonSet::new().with(Button::Down)));
+        assert!(!set.contains_all(ButtonSet::new().with(Button::Up).with(Button::Down)));
+
+        set.remove(Button::Button1);
+        assert!(set.iter().eq([Button::Down]));
+        set.remove(Button::Down);
+        assert!(set.is_empty());
+    }
+}
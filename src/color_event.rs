@@ -1,13 +1,19 @@
 //! Implements the [`ColorEvent`] and its parsing from the protocol.
 
-use super::ProtocolParseError;
+use super::text::{TextParseError, Tokens};
+use super::{ControllerDataPackageType, ProtocolParseError};
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 #[cfg(feature = "rgb")]
 use rgb::RGB8;
 
 /// Represents a color event from the protocol.
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub struct ColorEvent {
     red: u8,
@@ -35,8 +41,61 @@ impl TryFrom<&[u8]> for ColorEvent {
     }
 }
 
+impl FromStr for ColorEvent {
+    type Err = TextParseError;
+
+    /// Parse the text form of the event (e.g. `color #ff2d39`), see [`text`](super::text).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Tokens::new(s);
+        tokens.expect_event(ControllerDataPackageType::Color)?;
+        let rgb = tokens
+            .next()?
+            .strip_prefix('#')
+            .filter(|rgb| rgb.len() == 6 && rgb.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|rgb| u32::from_str_radix(rgb, 16).ok())
+            .ok_or(tokens.invalid())?;
+        tokens.end()?;
+        Ok(ColorEvent {
+            red: (rgb >> 16) as u8,
+            green: (rgb >> 8) as u8,
+            blue: rgb as u8,
+        })
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for ColorEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "color #{:02x}{:02x}{:02x}",
+            self.red, self.green, self.blue
+        )
+    }
+}
+
 #[allow(missing_docs)] // the names are already obvious enough
 impl ColorEvent {
+    /// Create the event, e.g. `ColorEvent::new(255, 45, 57)`.
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    pub const fn with_red(mut self, red: u8) -> Self {
+        self.red = red;
+        self
+    }
+
+    pub const fn with_green(mut self, green: u8) -> Self {
+        self.green = green;
+        self
+    }
+
+    pub const fn with_blue(mut self, blue: u8) -> Self {
+        self.blue = blue;
+        self
+    }
+
     pub fn red(&self) -> u8 {
         self.red
     }
@@ -79,6 +138,45 @@ mod tests {
         assert_eq!(ColorEvent::try_from(input), Ok(expected));
     }
 
+    #[test]
+    fn test_text_form() {
+        let text = "color #ff2d39";
+        let event = ColorEvent {
+            red: 255,
+            green: 45,
+            blue: 57,
+        };
+
+        assert_eq!(text.parse(), Ok(event));
+        assert_eq!("color #FF2D39".parse(), Ok(event));
+        #[cfg(feature = "fmt")]
+        {
+            extern crate alloc;
+            use alloc::string::ToString;
+            assert_eq!(event.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_invalid_text_form() {
+        use crate::text::TextParseError;
+
+        for text in [
+            "color",
+            "color ff2d39",
+            "color #ff2d3",
+            "color #+f2d39",
+            "color #gg2d39",
+        ] {
+            assert_eq!(
+                text.parse::<ColorEvent>(),
+                Err(TextParseError::InvalidValue(1)),
+                "{}",
+                text
+            );
+        }
+    }
+
     #[test]
     #[cfg(feature = "rgb")]
     fn test_into_rgb8() {
@@ -92,4 +190,21 @@ mod tests {
         let result: RGB8 = input.into();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_new() {
+        const EVENT: ColorEvent = ColorEvent::new(1, 2, 3);
+        assert_eq!(
+            EVENT,
+            ColorEvent {
+                red: 1,
+                green: 2,
+                blue: 3
+            }
+        );
+        assert_eq!(
+            EVENT.with_red(255).with_green(45).with_blue(57),
+            ColorEvent::try_from(b"\xff-9".as_slice()).unwrap()
+        );
+    }
 }
@@ -0,0 +1,376 @@
+//! Implements the [`DifferentialDrive`] which turns the arrows or the tilt of the phone into the speeds of the two
+//! motors of a robot.
+
+#[cfg(feature = "accelerometer_event")]
+use super::accelerometer_event::AccelerometerEvent;
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::button_states::ButtonSet;
+use super::dpad::Direction;
+use super::time::{Duration, Instant};
+use super::{abs_f32, ControllerEvent, ProtocolParseError};
+
+/// The speeds of the left and right motor, from -1 (full speed backwards) to 1 (full speed forwards).
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct MotorCommand {
+    left: f32,
+    right: f32,
+}
+
+impl MotorCommand {
+    /// Both motors are stopped.
+    pub const STOP: MotorCommand = MotorCommand::new(0.0, 0.0);
+
+    /// Create the command, e.g. `MotorCommand::new(1.0, -1.0)` to turn right in place.
+    pub const fn new(left: f32, right: f32) -> Self {
+        Self { left, right }
+    }
+
+    /// The speed of the left motor.
+    pub fn left(&self) -> f32 {
+        self.left
+    }
+
+    /// The speed of the right motor.
+    pub fn right(&self) -> f32 {
+        self.right
+    }
+}
+
+/// Mixes the arrows (or the tilt of the phone) into the [`MotorCommand`] of a robot with two driven wheels.
+///
+/// * up & down drive forwards & backwards, left & right turn. The diagonals drive a curve around the inner wheel.
+/// * with turn in place (the default) left & right spin the robot around its center, otherwise it pivots around the
+///   inner wheel.
+/// * with a tilt button the robot is driven by the tilt of the phone (from [`AccelerometerEvent`]s) while the button
+///   is held, tilting forward (positive y) drives forward and tilting to the right (positive x) turns right. The
+///   button acts as a dead man's switch. The arrows take precedence over the tilt.
+/// * the speeds are limited to the max speed. With a ramp the speeds are increased gradually by
+///   [`DifferentialDrive::update`], taking the ramp duration from stop to full speed. Slowing down is never ramped and
+///   the motors stop right away once all arrows (or the tilt button) are released or [`DifferentialDrive::release_all`]
+///   is called, e.g. when the connection has been lost.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::differential_drive::{DifferentialDrive, MotorCommand};
+/// # use adafruit_bluefruit_protocol::time::{Duration, Instant};
+/// # use adafruit_bluefruit_protocol::Parser;
+/// let mut drive = DifferentialDrive::new()
+///     .with_max_speed(0.8)
+///     .with_ramp(Duration::from_millis(1000));
+/// drive.update(Instant::from_millis(0));
+/// // up is pressed
+/// drive.apply_all(Parser::new(b"!B516"));
+///
+/// assert_eq!(drive.update(Instant::from_millis(500)), MotorCommand::new(0.5, 0.5));
+/// assert_eq!(drive.update(Instant::from_millis(1000)), MotorCommand::new(0.8, 0.8));
+///
+/// // up is released
+/// drive.apply_all(Parser::new(b"!B507"));
+/// assert_eq!(drive.command(), MotorCommand::STOP);
+/// ```
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DifferentialDrive {
+    max_speed: f32,
+    ramp: Duration,
+    turn_in_place: bool,
+    tilt_button: Option<Button>,
+    tilt_range: f32,
+    dead_zone: f32,
+    buttons: ButtonSet,
+    tilt: Option<(f32, f32)>,
+    command: MotorCommand,
+    last_update: Option<Instant>,
+}
+
+impl DifferentialDrive {
+    /// Create a drive with full speed, no ramp, turn in place and without tilt control.
+    pub const fn new() -> Self {
+        Self {
+            max_speed: 1.0,
+            ramp: Duration::ZERO,
+            turn_in_place: true,
+            tilt_button: None,
+            tilt_range: 0.5,
+            dead_zone: 0.1,
+            buttons: ButtonSet::new(),
+            tilt: None,
+            command: MotorCommand::STOP,
+            last_update: None,
+        }
+    }
+
+    /// Set the maximum speed of the motors (from 0 to 1).
+    pub const fn with_max_speed(mut self, max_speed: f32) -> Self {
+        self.max_speed = max_speed;
+        self
+    }
+
+    /// Set the time it takes to accelerate from stop to full speed. With [`Duration::ZERO`] the speeds change at once.
+    pub const fn with_ramp(mut self, ramp: Duration) -> Self {
+        self.ramp = ramp;
+        self
+    }
+
+    /// Set whether left & right spin the robot in place (`true`) or pivot it around the inner wheel (`false`).
+    pub const fn with_turn_in_place(mut self, turn_in_place: bool) -> Self {
+        self.turn_in_place = turn_in_place;
+        self
+    }
+
+    /// Drive with the tilt of the phone while the button is held, `None` disables tilt control.
+    pub const fn with_tilt_button(mut self, button: Option<Button>) -> Self {
+        self.tilt_button = button;
+        self
+    }
+
+    /// Set the tilt which results in full speed (default: 0.5, i.e. 30° if the app sends the acceleration in g) and the
+    /// dead zone around the neutral position (default: 0.1) within which the tilt is ignored.
+    pub const fn with_tilt_range(mut self, range: f32, dead_zone: f32) -> Self {
+        self.tilt_range = range;
+        self.dead_zone = dead_zone;
+        self
+    }
+
+    /// Update the inputs with the event, all other events are ignored.
+    pub fn apply(&mut self, event: &ControllerEvent) {
+        match event {
+            ControllerEvent::ButtonEvent(event) => self.apply_button(event),
+            #[cfg(feature = "accelerometer_event")]
+            ControllerEvent::AccelerometerEvent(event) => self.apply_accelerometer(event),
+            #[allow(unreachable_patterns)] // depends on the selected events
+            _ => {}
+        }
+    }
+
+    /// Update the inputs with all events from the parser. Errors are skipped, their number is returned.
+    pub fn apply_all<I>(&mut self, events: I) -> usize
+    where
+        I: IntoIterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+    {
+        let mut errors = 0;
+        for event in events {
+            match event {
+                Ok(event) => self.apply(&event),
+                Err(_) => errors += 1,
+            }
+        }
+        errors
+    }
+
+    /// Update the inputs with the button event.
+    pub fn apply_button(&mut self, event: &ButtonEvent) {
+        match event.state() {
+            ButtonState::Pressed => self.buttons.insert(*event.button()),
+            ButtonState::Released => self.buttons.remove(*event.button()),
+        }
+        self.stop_if_idle();
+    }
+
+    /// Update the tilt with the accelerometer event.
+    #[cfg(feature = "accelerometer_event")]
+    pub fn apply_accelerometer(&mut self, event: &AccelerometerEvent) {
+        self.tilt = Some((event.x(), event.y()));
+        self.stop_if_idle();
+    }
+
+    /// Stop the motors right away and release all inputs, e.g. when the connection has been lost.
+    pub fn release_all(&mut self) {
+        self.buttons = ButtonSet::new();
+        self.tilt = None;
+        self.command = MotorCommand::STOP;
+    }
+
+    /// Move the speeds towards the inputs (see [`DifferentialDrive`]) and return the new command.
+    ///
+    /// Call this regularly (e.g. in each iteration of the control loop) and send the command to the motors.
+    pub fn update(&mut self, now: Instant) -> MotorCommand {
+        let target = self.target();
+        let step = match self.last_update {
+            _ if self.ramp == Duration::ZERO => f32::INFINITY,
+            Some(last) => {
+                now.saturating_duration_since(last).as_micros() as f32
+                    / self.ramp.as_micros() as f32
+            }
+            None => 0.0,
+        };
+        self.last_update = Some(now);
+        self.command = MotorCommand::new(
+            ramp(self.command.left, target.left, step),
+            ramp(self.command.right, target.right, step),
+        );
+        self.command
+    }
+
+    /// The latest command, without updating it.
+    pub fn command(&self) -> MotorCommand {
+        self.command
+    }
+
+    /// The command for the current inputs without ramping.
+    pub fn target(&self) -> MotorCommand {
+        let direction = Direction::from_arrows(
+            self.buttons.contains(Button::Up),
+            self.buttons.contains(Button::Down),
+            self.buttons.contains(Button::Left),
+            self.buttons.contains(Button::Right),
+        );
+        let (x, y) = match (self.tilt_button, self.tilt) {
+            _ if direction != Direction::Center => direction.vector(),
+            (Some(button), Some((x, y))) if self.buttons.contains(button) => {
+                (self.scale_tilt(x), self.scale_tilt(y))
+            }
+            _ => (0.0, 0.0),
+        };
+        let (left, right) = if y == 0.0 && !self.turn_in_place {
+            if x > 0.0 {
+                (x, 0.0)
+            } else {
+                (0.0, -x)
+            }
+        } else {
+            (y + x, y - x)
+        };
+        // keep the ratio of the speeds if one of them is out of range
+        let max = abs_f32(left).max(abs_f32(right)).max(1.0);
+        MotorCommand::new(left / max * self.max_speed, right / max * self.max_speed)
+    }
+
+    /// Maps the tilt of an axis to -1..1 using the dead zone and the range.
+    fn scale_tilt(&self, tilt: f32) -> f32 {
+        let distance = abs_f32(tilt) - self.dead_zone;
+        let span = self.tilt_range - self.dead_zone;
+        let value = if distance.is_nan() || distance <= 0.0 {
+            0.0
+        } else if span > 0.0 && distance < span {
+            distance / span
+        } else {
+            1.0
+        };
+        if tilt < 0.0 {
+            -value
+        } else {
+            value
+        }
+    }
+
+    fn stop_if_idle(&mut self) {
+        if self.target() == MotorCommand::STOP {
+            self.command = MotorCommand::STOP;
+        }
+    }
+}
+
+impl Default for DifferentialDrive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Moves the speed by at most `step` towards the target, slowing down (and reversing through 0) is not limited.
+fn ramp(current: f32, target: f32, step: f32) -> f32 {
+    let current = if current * target > 0.0 { current } else { 0.0 };
+    if abs_f32(target) <= abs_f32(current) + step {
+        target
+    } else if target > 0.0 {
+        current + step
+    } else {
+        current - step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::differential_drive::{DifferentialDrive, MotorCommand};
+    use crate::time::{Duration, Instant};
+
+    fn apply(drive: &mut DifferentialDrive, button: Button, state: ButtonState) {
+        drive.apply_button(&ButtonEvent::new(button, state));
+    }
+
+    fn command(left: f32, right: f32) -> MotorCommand {
+        MotorCommand::new(left, right)
+    }
+
+    #[test]
+    fn test_arrows() {
+        let mut drive = DifferentialDrive::new();
+        let now = Instant::from_millis(0);
+        assert_eq!(drive.update(now), MotorCommand::STOP);
+
+        apply(&mut drive, Button::Down, ButtonState::Pressed);
+        assert_eq!(drive.update(now), command(-1.0, -1.0));
+        apply(&mut drive, Button::Right, ButtonState::Pressed);
+        assert_eq!(drive.update(now), command(0.0, -1.0));
+        apply(&mut drive, Button::Down, ButtonState::Released);
+        assert_eq!(drive.update(now), command(1.0, -1.0));
+        apply(&mut drive, Button::Right, ButtonState::Released);
+        assert_eq!(drive.command(), MotorCommand::STOP);
+    }
+
+    #[test]
+    fn test_pivot_and_max_speed() {
+        let mut drive = DifferentialDrive::new()
+            .with_turn_in_place(false)
+            .with_max_speed(0.5);
+
+        apply(&mut drive, Button::Left, ButtonState::Pressed);
+        assert_eq!(drive.target(), command(0.0, 0.5));
+        apply(&mut drive, Button::Up, ButtonState::Pressed);
+        let target = drive.target();
+        assert_eq!(target.left(), 0.0);
+        assert_eq!(target.right(), 0.5);
+        apply(&mut drive, Button::Button1, ButtonState::Pressed);
+        assert_eq!(drive.target(), target);
+    }
+
+    #[test]
+    fn test_ramp() {
+        let mut drive = DifferentialDrive::new().with_ramp(Duration::from_millis(100));
+        drive.update(Instant::from_millis(0));
+
+        apply(&mut drive, Button::Up, ButtonState::Pressed);
+        assert_eq!(drive.command(), MotorCommand::STOP);
+        assert_eq!(drive.update(Instant::from_millis(25)), command(0.25, 0.25));
+        // turning right slows down the right wheel at once
+        apply(&mut drive, Button::Right, ButtonState::Pressed);
+        let ramped = drive.update(Instant::from_millis(50));
+        assert_eq!(ramped.left(), 0.5);
+        assert_eq!(ramped.right(), 0.0);
+        assert_eq!(drive.update(Instant::from_millis(1000)), command(1.0, 0.0));
+
+        // releasing the connection stops at once
+        drive.release_all();
+        assert_eq!(drive.command(), MotorCommand::STOP);
+        assert_eq!(drive.update(Instant::from_millis(1100)), MotorCommand::STOP);
+    }
+
+    #[test]
+    #[cfg(feature = "accelerometer_event")]
+    fn test_tilt() {
+        use crate::accelerometer_event::AccelerometerEvent;
+        use crate::ControllerEvent;
+
+        let mut drive = DifferentialDrive::new()
+            .with_tilt_button(Some(Button::Button1))
+            .with_tilt_range(0.5, 0.25);
+        let now = Instant::from_millis(0);
+        let tilt = ControllerEvent::AccelerometerEvent(AccelerometerEvent::new(0.125, 0.375, 0.9));
+        drive.apply(&tilt);
+        assert_eq!(drive.update(now), MotorCommand::STOP);
+
+        apply(&mut drive, Button::Button1, ButtonState::Pressed);
+        assert_eq!(drive.update(now), command(0.5, 0.5));
+        // the arrows take precedence
+        apply(&mut drive, Button::Left, ButtonState::Pressed);
+        assert_eq!(drive.update(now), command(-1.0, 1.0));
+        apply(&mut drive, Button::Left, ButtonState::Released);
+
+        apply(&mut drive, Button::Button1, ButtonState::Released);
+        assert_eq!(drive.command(), MotorCommand::STOP);
+    }
+}
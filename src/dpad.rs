@@ -0,0 +1,245 @@
+//! Implements the [`DPad`] which turns the arrow buttons into an 8-way [`Direction`] and a joystick-like vector.
+
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::button_states::ButtonSet;
+use super::time::{Duration, Instant};
+use super::ControllerEvent;
+use core::f32::consts::FRAC_1_SQRT_2;
+
+/// The direction of the arrow buttons.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[allow(missing_docs)] // the names are already obvious enough
+pub enum Direction {
+    /// No arrow is held (or only opposite ones).
+    #[default]
+    Center,
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Direction {
+    /// The direction of the held arrows, opposite arrows cancel each other out.
+    pub const fn from_arrows(up: bool, down: bool, left: bool, right: bool) -> Self {
+        match (up && !down, down && !up, left && !right, right && !left) {
+            (true, _, true, _) => Direction::UpLeft,
+            (true, _, _, true) => Direction::UpRight,
+            (true, _, _, _) => Direction::Up,
+            (_, true, true, _) => Direction::DownLeft,
+            (_, true, _, true) => Direction::DownRight,
+            (_, true, _, _) => Direction::Down,
+            (_, _, true, _) => Direction::Left,
+            (_, _, _, true) => Direction::Right,
+            _ => Direction::Center,
+        }
+    }
+
+    /// The unit vector `(x, y)` of the direction (with `y` pointing up), `(0, 0)` for [`Direction::Center`].
+    pub fn vector(&self) -> (f32, f32) {
+        match self {
+            Direction::Center => (0.0, 0.0),
+            Direction::Up => (0.0, 1.0),
+            Direction::UpRight => (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            Direction::Right => (1.0, 0.0),
+            Direction::DownRight => (FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+            Direction::Down => (0.0, -1.0),
+            Direction::DownLeft => (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+            Direction::Left => (-1.0, 0.0),
+            Direction::UpLeft => (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+        }
+    }
+}
+
+/// Tracks the arrow buttons to use them like a joystick.
+///
+/// The [`DPad::vector`] has a length of 1 (or 0 if no arrow is held). With a ramp the length instead grows linearly
+/// from 0 to 1 over the ramp duration once an arrow is pressed, so a held arrow accelerates smoothly. Changing the
+/// direction while an arrow is held does not restart the ramp.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::dpad::{DPad, Direction};
+/// # use adafruit_bluefruit_protocol::time::{Duration, Instant};
+/// # use adafruit_bluefruit_protocol::Parser;
+/// let mut dpad = DPad::new().with_ramp(Duration::from_millis(200));
+/// // up and right are pressed
+/// for event in Parser::new(b"!B516!B813") {
+///     dpad.apply(&event.unwrap(), Instant::from_millis(0));
+/// }
+///
+/// assert_eq!(dpad.direction(), Direction::UpRight);
+/// assert_eq!(dpad.vector(Instant::from_millis(0)), (0.0, 0.0));
+/// let (x, y) = dpad.vector(Instant::from_millis(100));
+/// assert!(x > 0.35 && x < 0.36 && x == y);
+/// assert_eq!(dpad.vector(Instant::from_millis(300)), Direction::UpRight.vector());
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DPad {
+    ramp: Duration,
+    arrows: ButtonSet,
+    active_since: Option<Instant>,
+}
+
+impl DPad {
+    /// Create a d-pad without ramp and with all arrows released.
+    pub const fn new() -> Self {
+        Self {
+            ramp: Duration::ZERO,
+            arrows: ButtonSet::new(),
+            active_since: None,
+        }
+    }
+
+    /// Set the time it takes for the vector to reach its full length after an arrow has been pressed.
+    pub const fn with_ramp(mut self, ramp: Duration) -> Self {
+        self.ramp = ramp;
+        self
+    }
+
+    /// Update the state with the event received at `now`, all other events are ignored.
+    pub fn apply(&mut self, event: &ControllerEvent, now: Instant) {
+        #[allow(irrefutable_let_patterns)] // if only `button_event` has been selected
+        if let ControllerEvent::ButtonEvent(event) = event {
+            self.apply_button(event, now);
+        }
+    }
+
+    /// Update the state with the button event received at `now`, the number buttons are ignored.
+    pub fn apply_button(&mut self, event: &ButtonEvent, now: Instant) {
+        let button = *event.button();
+        if !matches!(
+            button,
+            Button::Up | Button::Down | Button::Left | Button::Right
+        ) {
+            return;
+        }
+        match event.state() {
+            ButtonState::Pressed => self.arrows.insert(button),
+            ButtonState::Released => self.arrows.remove(button),
+        }
+        self.update_ramp(now);
+    }
+
+    /// Release all arrows, e.g. when the connection has been lost.
+    pub fn release_all(&mut self) {
+        self.arrows = ButtonSet::new();
+        self.active_since = None;
+    }
+
+    /// The direction of the held arrows.
+    pub fn direction(&self) -> Direction {
+        Direction::from_arrows(
+            self.arrows.contains(Button::Up),
+            self.arrows.contains(Button::Down),
+            self.arrows.contains(Button::Left),
+            self.arrows.contains(Button::Right),
+        )
+    }
+
+    /// The vector `(x, y)` at `now` (with `y` pointing up), see [`DPad`].
+    pub fn vector(&self, now: Instant) -> (f32, f32) {
+        let (x, y) = self.direction().vector();
+        let scale = match self.active_since {
+            Some(since) if now - since < self.ramp => {
+                (now - since).as_micros() as f32 / self.ramp.as_micros() as f32
+            }
+            _ => 1.0,
+        };
+        (x * scale, y * scale)
+    }
+
+    fn update_ramp(&mut self, now: Instant) {
+        if self.direction() == Direction::Center {
+            self.active_since = None;
+        } else if self.active_since.is_none() {
+            self.active_since = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::dpad::{DPad, Direction};
+    use crate::time::{Duration, Instant};
+    use core::f32::consts::FRAC_1_SQRT_2;
+
+    fn apply(dpad: &mut DPad, button: Button, state: ButtonState, millis: u64) {
+        dpad.apply_button(
+            &ButtonEvent::new(button, state),
+            Instant::from_millis(millis),
+        );
+    }
+
+    #[test]
+    fn test_direction_from_arrows() {
+        assert_eq!(
+            Direction::from_arrows(false, false, false, false),
+            Direction::Center
+        );
+        assert_eq!(
+            Direction::from_arrows(true, true, false, false),
+            Direction::Center
+        );
+        assert_eq!(
+            Direction::from_arrows(true, true, true, false),
+            Direction::Left
+        );
+        assert_eq!(
+            Direction::from_arrows(false, true, false, true),
+            Direction::DownRight
+        );
+        assert_eq!(
+            Direction::from_arrows(true, false, true, true),
+            Direction::Up
+        );
+    }
+
+    #[test]
+    fn test_without_ramp() {
+        let mut dpad = DPad::new();
+        let now = Instant::from_millis(0);
+        assert_eq!(dpad.vector(now), (0.0, 0.0));
+
+        apply(&mut dpad, Button::Down, ButtonState::Pressed, 0);
+        assert_eq!(dpad.vector(now), (0.0, -1.0));
+        apply(&mut dpad, Button::Left, ButtonState::Pressed, 0);
+        assert_eq!(dpad.vector(now), (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
+        apply(&mut dpad, Button::Button1, ButtonState::Pressed, 0);
+        assert_eq!(dpad.direction(), Direction::DownLeft);
+
+        dpad.release_all();
+        assert_eq!(dpad.direction(), Direction::Center);
+    }
+
+    #[test]
+    fn test_ramp() {
+        let mut dpad = DPad::new().with_ramp(Duration::from_millis(100));
+
+        apply(&mut dpad, Button::Right, ButtonState::Pressed, 1000);
+        assert_eq!(dpad.vector(Instant::from_millis(1025)), (0.25, 0.0));
+        // changing the direction does not restart the ramp
+        apply(&mut dpad, Button::Up, ButtonState::Pressed, 1050);
+        assert_eq!(
+            dpad.vector(Instant::from_millis(1050)),
+            (FRAC_1_SQRT_2 * 0.5, FRAC_1_SQRT_2 * 0.5)
+        );
+        apply(&mut dpad, Button::Right, ButtonState::Released, 1060);
+        assert_eq!(dpad.vector(Instant::from_millis(1200)), (0.0, 1.0));
+
+        // releasing all arrows restarts it
+        apply(&mut dpad, Button::Up, ButtonState::Released, 1300);
+        apply(&mut dpad, Button::Up, ButtonState::Pressed, 1400);
+        assert_eq!(dpad.vector(Instant::from_millis(1450)), (0.0, 0.5));
+    }
+}
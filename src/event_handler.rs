@@ -0,0 +1,170 @@
+//! Implements the [`EventHandler`] trait and [`dispatch`] which can be used instead of matching on [`ControllerEvent`].
+
+#[cfg(feature = "accelerometer_event")]
+use super::accelerometer_event::AccelerometerEvent;
+#[cfg(feature = "button_event")]
+use super::button_event::ButtonEvent;
+#[cfg(feature = "color_event")]
+use super::color_event::ColorEvent;
+#[cfg(feature = "gyro_event")]
+use super::gyro_event::GyroEvent;
+#[cfg(feature = "location_event")]
+use super::location_event::LocationEvent;
+#[cfg(feature = "magnetometer_event")]
+use super::magnetometer_event::MagnetometerEvent;
+#[cfg(feature = "quaternion_event")]
+use super::quaternion_event::QuaternionEvent;
+use super::{ControllerEvent, ProtocolParseError};
+
+/// Handles the events produced by the [`Parser`](super::Parser), see [`dispatch`].
+///
+/// All methods have a default implementation which does nothing, so you only have to implement the ones you're interested in.
+/// The methods are only available if the corresponding event has been selected as a feature.
+pub trait EventHandler {
+    /// Called for each received [`ButtonEvent`].
+    #[cfg(feature = "button_event")]
+    fn on_button(&mut self, _event: ButtonEvent) {}
+
+    /// Called for each received [`ColorEvent`].
+    #[cfg(feature = "color_event")]
+    fn on_color(&mut self, _event: ColorEvent) {}
+
+    /// Called for each received [`QuaternionEvent`].
+    #[cfg(feature = "quaternion_event")]
+    fn on_quaternion(&mut self, _event: QuaternionEvent) {}
+
+    /// Called for each received [`AccelerometerEvent`].
+    #[cfg(feature = "accelerometer_event")]
+    fn on_accelerometer(&mut self, _event: AccelerometerEvent) {}
+
+    /// Called for each received [`GyroEvent`].
+    #[cfg(feature = "gyro_event")]
+    fn on_gyro(&mut self, _event: GyroEvent) {}
+
+    /// Called for each received [`MagnetometerEvent`].
+    #[cfg(feature = "magnetometer_event")]
+    fn on_magnetometer(&mut self, _event: MagnetometerEvent) {}
+
+    /// Called for each received [`LocationEvent`].
+    #[cfg(feature = "location_event")]
+    fn on_location(&mut self, _event: LocationEvent) {}
+
+    /// Called for each message which could not be parsed.
+    fn on_error(&mut self, _error: ProtocolParseError) {}
+}
+
+/// Pass all events (and errors) from the parser to the matching method of the handler.
+///
+/// ## Example
+/// ```
+/// # #[cfg(feature = "button_event")]
+/// # {
+/// # use adafruit_bluefruit_protocol::button_event::{ButtonEvent, ButtonState};
+/// # use adafruit_bluefruit_protocol::event_handler::{dispatch, EventHandler};
+/// # use adafruit_bluefruit_protocol::Parser;
+/// #[derive(Default)]
+/// struct PressCounter {
+///     presses: usize,
+/// }
+///
+/// impl EventHandler for PressCounter {
+///     fn on_button(&mut self, event: ButtonEvent) {
+///         if event.state() == &ButtonState::Pressed {
+///             self.presses += 1;
+///         }
+///     }
+/// }
+///
+/// let mut handler = PressCounter::default();
+/// dispatch(Parser::new(b"!B11:!B10;!B219"), &mut handler);
+/// assert_eq!(handler.presses, 2);
+/// # }
+/// ```
+pub fn dispatch<I, H>(events: I, handler: &mut H)
+where
+    I: IntoIterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+    H: EventHandler + ?Sized,
+{
+    for event in events {
+        match event {
+            #[cfg(feature = "button_event")]
+            Ok(ControllerEvent::ButtonEvent(event)) => handler.on_button(event),
+            #[cfg(feature = "color_event")]
+            Ok(ControllerEvent::ColorEvent(event)) => handler.on_color(event),
+            #[cfg(feature = "quaternion_event")]
+            Ok(ControllerEvent::QuaternionEvent(event)) => handler.on_quaternion(event),
+            #[cfg(feature = "accelerometer_event")]
+            Ok(ControllerEvent::AccelerometerEvent(event)) => handler.on_accelerometer(event),
+            #[cfg(feature = "gyro_event")]
+            Ok(ControllerEvent::GyroEvent(event)) => handler.on_gyro(event),
+            #[cfg(feature = "magnetometer_event")]
+            Ok(ControllerEvent::MagnetometerEvent(event)) => handler.on_magnetometer(event),
+            #[cfg(feature = "location_event")]
+            Ok(ControllerEvent::LocationEvent(event)) => handler.on_location(event),
+            Err(e) => handler.on_error(e),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "button_event", feature = "color_event"))]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent};
+    use crate::color_event::ColorEvent;
+    use crate::event_handler::{dispatch, EventHandler};
+    use crate::{Parser, ProtocolParseError};
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        last_button: Option<Button>,
+        last_color: Option<ColorEvent>,
+        errors: usize,
+    }
+
+    impl EventHandler for RecordingHandler {
+        fn on_button(&mut self, event: ButtonEvent) {
+            self.last_button = Some(*event.button());
+        }
+
+        fn on_color(&mut self, event: ColorEvent) {
+            self.last_color = Some(event);
+        }
+
+        fn on_error(&mut self, _error: ProtocolParseError) {
+            self.errors += 1;
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let input = b"!B417!C\xff-96!B138";
+        let mut handler = RecordingHandler::default();
+
+        dispatch(Parser::new(input), &mut handler);
+
+        assert_eq!(handler.last_button, Some(Button::Button4));
+        assert_eq!(handler.last_color.map(|c| c.red()), Some(255));
+        assert_eq!(handler.errors, 1);
+    }
+
+    #[test]
+    fn test_dispatch_ignores_unhandled_events() {
+        #[derive(Default)]
+        struct ColorCounter {
+            colors: usize,
+        }
+
+        impl EventHandler for ColorCounter {
+            fn on_color(&mut self, _event: ColorEvent) {
+                self.colors += 1;
+            }
+        }
+
+        let mut parser = Parser::new(b"!B11:!C\xff-96!B10;");
+        let mut handler = ColorCounter::default();
+
+        dispatch(parser.by_ref(), &mut handler);
+
+        assert_eq!(handler.colors, 1);
+        assert_eq!(parser.next(), None);
+    }
+}
@@ -0,0 +1,524 @@
+//! Implements the [`EventQueue`] to hand over events from an interrupt handler to a task.
+
+use super::{
+    parse_command, ControllerDataPackageType, ControllerEvent, ParseOptions, MAX_FRAME_LENGTH,
+};
+use core::fmt::{Debug, Formatter};
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+use heapless::spsc::{Consumer, Producer, Queue};
+
+/// An event together with its position in the order in which the events have been enqueued.
+#[derive(Debug, Copy, Clone)]
+struct QueuedEvent {
+    sequence: u32,
+    event: ControllerEvent,
+}
+
+/// The number of words needed to store the frame of an event.
+const FRAME_WORDS: usize = MAX_FRAME_LENGTH.div_ceil(4);
+
+/// A slot of the ring buffer for sensor events.
+///
+/// The event is stored as its frame in atomics, so the producer can overwrite the slot while the consumer is reading
+/// it. The stamp tells the consumer whether it has read a complete event and which one.
+struct SensorSlot {
+    /// `2 * index + 1` while the event with the index is being written, `2 * index + 2` once it is complete.
+    stamp: AtomicU32,
+    sequence: AtomicU32,
+    frame: [AtomicU32; FRAME_WORDS],
+}
+
+impl SensorSlot {
+    const fn new() -> Self {
+        Self {
+            stamp: AtomicU32::new(0),
+            sequence: AtomicU32::new(0),
+            frame: [const { AtomicU32::new(0) }; FRAME_WORDS],
+        }
+    }
+}
+
+/// The stamp of a slot once the event with the index has been written completely.
+fn complete_stamp(index: u32) -> u32 {
+    index.wrapping_mul(2).wrapping_add(2)
+}
+
+/// The number of events which could not be enqueued, see [`EventConsumer::overflows`].
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OverflowCounters {
+    /// Sensor events which have been dropped because newer sensor events arrived while the sensor queue was full.
+    pub dropped_sensor_events: u32,
+    /// Button events which have been rejected (and returned to the producer) because the queue for button events was full.
+    pub rejected_button_events: u32,
+}
+
+/// A bounded, lock-free single producer single consumer queue for [`ControllerEvent`]s.
+///
+/// Button events and sensor events (all other events) are kept in separate queues which can each hold up to `N - 1`
+/// events, so that a flood of sensor events can never push out a button event. The events are still dequeued in the
+/// order in which they have been enqueued.
+///
+/// If the sensor queue is full, the oldest sensor event is dropped to make room for the new one: sensor events are
+/// sent continuously, so only the latest readings are of interest. Button events are never dropped silently: if their
+/// queue is full, [`EventProducer::enqueue`] returns the event so that the caller can decide what to do (e.g. signal
+/// the consumer). Both cases are counted, see [`EventConsumer::overflows`].
+///
+/// Call [`EventQueue::split`] to get the [`EventProducer`] (e.g. for the UART interrupt handler) and the
+/// [`EventConsumer`] (e.g. for the task handling the events).
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::event_queue::EventQueue;
+/// # use adafruit_bluefruit_protocol::Parser;
+/// let mut queue = EventQueue::<4>::new();
+/// let (mut producer, mut consumer) = queue.split();
+///
+/// for event in Parser::new(b"!B11:!C\xff-96").flatten() {
+///     producer.enqueue(event).unwrap();
+/// }
+///
+/// assert_eq!(consumer.len(), 2);
+/// while let Some(event) = consumer.dequeue() {
+///     // handle the event
+/// }
+/// assert_eq!(consumer.overflows().dropped_sensor_events, 0);
+/// ```
+pub struct EventQueue<const N: usize> {
+    buttons: Queue<QueuedEvent, N>,
+    sensors: [SensorSlot; N],
+    /// The number of sensor events which have been written by the producer.
+    sensors_written: AtomicU32,
+    /// The number of sensor events which have been read (or skipped) by the consumer.
+    sensors_read: AtomicU32,
+    dropped_sensor_events: AtomicU32,
+    rejected_button_events: AtomicU32,
+}
+
+impl<const N: usize> EventQueue<N> {
+    /// Create an empty queue. This is `const` so that the queue can be placed in a `static`.
+    pub const fn new() -> Self {
+        Self {
+            buttons: Queue::new(),
+            sensors: [const { SensorSlot::new() }; N],
+            sensors_written: AtomicU32::new(0),
+            sensors_read: AtomicU32::new(0),
+            dropped_sensor_events: AtomicU32::new(0),
+            rejected_button_events: AtomicU32::new(0),
+        }
+    }
+
+    /// Split the queue into the producer and the consumer.
+    pub fn split(&mut self) -> (EventProducer<'_, N>, EventConsumer<'_, N>) {
+        let (button_producer, button_consumer) = self.buttons.split();
+        let sensors = &self.sensors;
+        let sensors_written = &self.sensors_written;
+        let sensors_read = &self.sensors_read;
+        let dropped_sensor_events = &self.dropped_sensor_events;
+        let rejected_button_events = &self.rejected_button_events;
+        (
+            EventProducer {
+                buttons: button_producer,
+                sensors,
+                sensors_written,
+                sensor_index: sensors_written.load(Ordering::Relaxed),
+                sequence: 0,
+                rejected_button_events,
+            },
+            EventConsumer {
+                buttons: button_consumer,
+                sensors,
+                sensors_written,
+                sensors_read,
+                sensor_index: sensors_read.load(Ordering::Relaxed),
+                dropped_sensor_events,
+                rejected_button_events,
+            },
+        )
+    }
+}
+
+impl<const N: usize> Default for EventQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Debug for EventQueue<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EventQueue")
+            .field("capacity", &N.saturating_sub(1))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for EventQueue<N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "EventQueue {{ capacity: {} }}", N.saturating_sub(1))
+    }
+}
+
+/// Add to the counter. Only one side writes each counter, so this does not need an atomic read-modify-write
+/// (which is not available on all targets, e.g. `thumbv6m-none-eabi`).
+fn increment(counter: &AtomicU32, by: u32) {
+    counter.store(
+        counter.load(Ordering::Relaxed).wrapping_add(by),
+        Ordering::Relaxed,
+    );
+}
+
+/// The producing end of an [`EventQueue`].
+pub struct EventProducer<'a, const N: usize> {
+    buttons: Producer<'a, QueuedEvent, N>,
+    sensors: &'a [SensorSlot; N],
+    sensors_written: &'a AtomicU32,
+    /// The index of the next sensor event, i.e. the number of sensor events written so far.
+    sensor_index: u32,
+    sequence: u32,
+    rejected_button_events: &'a AtomicU32,
+}
+
+impl<const N: usize> EventProducer<'_, N> {
+    /// Add the event to the queue.
+    ///
+    /// Returns the event if it is a button event and the queue for button events is full.
+    /// If the queue for sensor events is full, the oldest sensor event is dropped, this is not reported as an error.
+    pub fn enqueue(&mut self, event: ControllerEvent) -> Result<(), ControllerEvent> {
+        let result = if is_button_event(&event) {
+            let queued = QueuedEvent {
+                sequence: self.sequence,
+                event,
+            };
+            self.buttons.enqueue(queued).map_err(|queued| {
+                increment(self.rejected_button_events, 1);
+                queued.event
+            })
+        } else {
+            self.write_sensor(&event);
+            Ok(())
+        };
+
+        self.sequence = self.sequence.wrapping_add(1);
+        result
+    }
+
+    /// Write the sensor event into the next slot, overwriting the oldest event if the queue is full.
+    fn write_sensor(&mut self, event: &ControllerEvent) {
+        if N == 0 {
+            return;
+        }
+        let index = self.sensor_index;
+        let slot = &self.sensors[index as usize % N];
+        let frame = event.to_frame();
+
+        slot.stamp
+            .store(index.wrapping_mul(2).wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.sequence.store(self.sequence, Ordering::Relaxed);
+        for (word, bytes) in slot.frame.iter().zip(frame.as_bytes().chunks(4)) {
+            let mut buffer = [0; 4];
+            buffer[..bytes.len()].copy_from_slice(bytes);
+            word.store(u32::from_le_bytes(buffer), Ordering::Relaxed);
+        }
+        slot.stamp.store(complete_stamp(index), Ordering::Release);
+
+        self.sensor_index = index.wrapping_add(1);
+        self.sensors_written
+            .store(self.sensor_index, Ordering::Release);
+    }
+}
+
+impl<const N: usize> Debug for EventProducer<'_, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EventProducer")
+            .field("sequence", &self.sequence)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for EventProducer<'_, N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "EventProducer {{ sequence: {} }}", self.sequence)
+    }
+}
+
+/// The consuming end of an [`EventQueue`].
+pub struct EventConsumer<'a, const N: usize> {
+    buttons: Consumer<'a, QueuedEvent, N>,
+    sensors: &'a [SensorSlot; N],
+    sensors_written: &'a AtomicU32,
+    sensors_read: &'a AtomicU32,
+    /// The index of the next sensor event to read.
+    sensor_index: u32,
+    dropped_sensor_events: &'a AtomicU32,
+    rejected_button_events: &'a AtomicU32,
+}
+
+impl<const N: usize> EventConsumer<'_, N> {
+    /// The number of events which can be queued per kind of event.
+    const CAPACITY: u32 = if N > 1 { (N - 1) as u32 } else { 0 };
+
+    /// Take the oldest event out of the queue.
+    pub fn dequeue(&mut self) -> Option<ControllerEvent> {
+        let sensor = self.peek_sensor();
+        let take_sensor = match (self.buttons.peek(), &sensor) {
+            (Some(button), Some(sensor)) => is_before(sensor.sequence, button.sequence),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+
+        if take_sensor {
+            self.advance_sensors(1);
+            sensor
+        } else {
+            self.buttons.dequeue()
+        }
+        .map(|queued| queued.event)
+    }
+
+    /// The number of events in the queue.
+    pub fn len(&self) -> usize {
+        self.buttons.len() + self.queued_sensors().min(Self::CAPACITY) as usize
+    }
+
+    /// Returns `true` if there is no event in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of events which could not be enqueued so far.
+    pub fn overflows(&self) -> OverflowCounters {
+        OverflowCounters {
+            dropped_sensor_events: self.dropped_sensor_events.load(Ordering::Relaxed),
+            rejected_button_events: self.rejected_button_events.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The number of sensor events which have been written but not yet read, incl. the ones which have been overwritten.
+    fn queued_sensors(&self) -> u32 {
+        self.sensors_written
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.sensor_index)
+    }
+
+    fn advance_sensors(&mut self, by: u32) {
+        self.sensor_index = self.sensor_index.wrapping_add(by);
+        self.sensors_read
+            .store(self.sensor_index, Ordering::Relaxed);
+    }
+
+    /// Read the oldest sensor event which has not been overwritten (yet), skipping the overwritten ones.
+    fn peek_sensor(&mut self) -> Option<QueuedEvent> {
+        loop {
+            let queued = self.queued_sensors();
+            if queued == 0 {
+                return None;
+            }
+            if queued > Self::CAPACITY {
+                let dropped = queued - Self::CAPACITY;
+                increment(self.dropped_sensor_events, dropped);
+                self.advance_sensors(dropped);
+                continue;
+            }
+
+            let index = self.sensor_index;
+            let slot = &self.sensors[index as usize % N];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            let sequence = slot.sequence.load(Ordering::Relaxed);
+            let mut frame = [0; FRAME_WORDS * 4];
+            for (bytes, word) in frame.chunks_mut(4).zip(&slot.frame) {
+                bytes.copy_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+            }
+            fence(Ordering::Acquire);
+            if stamp != complete_stamp(index) || slot.stamp.load(Ordering::Relaxed) != stamp {
+                // the producer has overwritten the event in the meantime, it is skipped by the next iteration
+                continue;
+            }
+
+            match decode(&frame) {
+                Some(event) => return Some(QueuedEvent { sequence, event }),
+                None => {
+                    // can not happen as the frame has been created from an event, just skip it to be sure
+                    increment(self.dropped_sensor_events, 1);
+                    self.advance_sensors(1);
+                }
+            }
+        }
+    }
+}
+
+impl<const N: usize> Debug for EventConsumer<'_, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EventConsumer")
+            .field("len", &self.len())
+            .field("overflows", &self.overflows())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for EventConsumer<'_, N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "EventConsumer {{ len: {}, overflows: {} }}",
+            self.len(),
+            self.overflows()
+        )
+    }
+}
+
+/// Parse the frame of a sensor event written by [`EventProducer::write_sensor`].
+fn decode(frame: &[u8]) -> Option<ControllerEvent> {
+    let command = ControllerDataPackageType::try_from(frame[1]).ok()?;
+    let len = command.data_len() + 3;
+    parse_command(command, &frame[..len], &ParseOptions::new()).ok()
+}
+
+/// Compare two sequence numbers, taking a wrap-around into account.
+fn is_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn is_button_event(event: &ControllerEvent) -> bool {
+    event.data_package_type() == ControllerDataPackageType::ButtonCommand
+}
+
+#[cfg(all(test, feature = "button_event", feature = "color_event"))]
+mod tests {
+    use crate::color_event::ColorEvent;
+    use crate::event_queue::{is_before, EventQueue, OverflowCounters};
+    use crate::{ControllerEvent, Parser};
+
+    fn events(input: &[u8]) -> impl Iterator<Item = ControllerEvent> + '_ {
+        Parser::new(input).map(|event| event.unwrap())
+    }
+
+    #[test]
+    fn test_keeps_order() {
+        let mut queue = EventQueue::<4>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let input = b"!C\xff-96!B11:!C\x01\x02\x03\x95!B10;";
+        for event in events(input) {
+            producer.enqueue(event).unwrap();
+        }
+
+        assert_eq!(consumer.len(), 4);
+        for expected in events(input) {
+            assert_eq!(consumer.dequeue(), Some(expected));
+        }
+        assert_eq!(consumer.dequeue(), None);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_sensor_events_are_dropped() {
+        let mut queue = EventQueue::<2>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        for event in events(b"!C\xff-96!C\x01\x02\x03\x95!B11:") {
+            producer.enqueue(event).unwrap();
+        }
+
+        let mut expected = events(b"!C\x01\x02\x03\x95!B11:");
+        assert_eq!(consumer.len(), 2);
+        assert_eq!(consumer.dequeue(), expected.next());
+        assert_eq!(consumer.dequeue(), expected.next());
+        assert_eq!(consumer.dequeue(), None);
+        assert_eq!(
+            consumer.overflows(),
+            OverflowCounters {
+                dropped_sensor_events: 1,
+                rejected_button_events: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_latest_sensor_events_are_kept() {
+        let mut queue = EventQueue::<4>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let readings: [ControllerEvent; 10] =
+            core::array::from_fn(|i| ControllerEvent::ColorEvent(ColorEvent::new(i as u8, 0, 0)));
+        for event in readings {
+            producer.enqueue(event).unwrap();
+        }
+
+        assert_eq!(consumer.len(), 3);
+        for expected in &readings[7..] {
+            assert_eq!(consumer.dequeue(), Some(*expected));
+        }
+        assert_eq!(consumer.dequeue(), None);
+        assert_eq!(consumer.overflows().dropped_sensor_events, 7);
+
+        // the queue can be used again after it has been wrapped around several times
+        producer.enqueue(readings[0]).unwrap();
+        assert_eq!(consumer.dequeue(), Some(readings[0]));
+    }
+
+    #[test]
+    fn test_sensor_events_are_dropped_between_button_events() {
+        let mut queue = EventQueue::<3>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let input = b"!C\x01\x02\x03\x95!B11:!C\xff-96!C\x00\x00\x00\x9b!B10;";
+        for event in events(input) {
+            producer.enqueue(event).unwrap();
+        }
+
+        // the first color has been pushed out by the later ones, the order is kept
+        let mut expected = events(input).skip(1);
+        for _ in 0..4 {
+            assert_eq!(consumer.dequeue(), expected.next());
+        }
+        assert_eq!(consumer.dequeue(), None);
+        assert_eq!(consumer.overflows().dropped_sensor_events, 1);
+    }
+
+    #[test]
+    fn test_split_again_keeps_queued_events() {
+        let mut queue = EventQueue::<3>::new();
+        {
+            let (mut producer, mut consumer) = queue.split();
+            for event in events(b"!C\xff-96!C\x01\x02\x03\x95") {
+                producer.enqueue(event).unwrap();
+            }
+            assert!(consumer.dequeue().is_some());
+        }
+
+        let (_, mut consumer) = queue.split();
+        assert_eq!(consumer.dequeue(), events(b"!C\x01\x02\x03\x95").next());
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_button_events_are_returned() {
+        let mut queue = EventQueue::<2>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let mut input = events(b"!B11:!B10;");
+        let pressed = input.next().unwrap();
+        let released = input.next().unwrap();
+        assert_eq!(producer.enqueue(pressed), Ok(()));
+        assert_eq!(producer.enqueue(released), Err(released));
+        assert_eq!(consumer.overflows().rejected_button_events, 1);
+
+        assert_eq!(consumer.dequeue(), Some(pressed));
+        assert_eq!(producer.enqueue(released), Ok(()));
+        assert_eq!(consumer.dequeue(), Some(released));
+    }
+
+    #[test]
+    fn test_sequence_wrap_around() {
+        assert!(is_before(1, 2));
+        assert!(!is_before(2, 1));
+        assert!(is_before(u32::MAX, 0));
+        assert!(!is_before(0, u32::MAX));
+    }
+}
@@ -0,0 +1,351 @@
+//! Support for property based testing and fuzzing of code which handles the events.
+//!
+//! * With the `arbitrary` feature all events, [`ControllerEvent`](crate::ControllerEvent), [`Frame`] and
+//!   [`CorruptedFrame`] implement [`arbitrary::Arbitrary`](https://docs.rs/arbitrary), e.g. for use with `cargo fuzz`.
+//! * With the `proptest` feature the [`strategies`] module provides [proptest](https://docs.rs/proptest) strategies
+//!   for the same types.
+//!
+//! The generated events can contain any float value, including `NaN`, so compare their frames
+//! (see [`ControllerEvent::to_frame`](crate::ControllerEvent::to_frame)) instead of the events if you need an exact
+//! match. Buttons which are not sent by the Adafruit app are generated as
+//! [`Button::Other`](crate::button_event::Button::Other), parse their frames with
+//! [`ButtonParseMode::Lenient`](crate::button_event::ButtonParseMode::Lenient) to get them back.
+
+use super::Frame;
+
+/// A modification which turns a valid [`Frame`] into an invalid one, see [`CorruptedFrame`].
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Corruption {
+    /// Flip a bit of a byte. The index is taken modulo the length of the frame, the bit modulo 8.
+    FlipBit {
+        /// The byte in which the bit is flipped.
+        index: usize,
+        /// The bit which is flipped.
+        bit: u8,
+    },
+    /// Replace a byte. The index is taken modulo the length of the frame.
+    ReplaceByte {
+        /// The byte which is replaced.
+        index: usize,
+        /// The new value of the byte.
+        value: u8,
+    },
+    /// Cut off the end of the frame, the parameter is the new length (modulo the length of the frame).
+    Truncate(usize),
+    /// Add the value to the CRC (`0` is treated as `1` so that the CRC always changes).
+    InvalidCrc(u8),
+}
+
+/// A [`Frame`] to which a [`Corruption`] has been applied.
+///
+/// Most corruptions result in an error when the frame is parsed, but some (e.g. replacing a byte with the same value)
+/// leave the frame valid. Use this to test that the code handling the input never panics.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CorruptedFrame {
+    original: Frame,
+    corruption: Corruption,
+    corrupted: Frame,
+}
+
+impl CorruptedFrame {
+    /// Apply the corruption to the frame.
+    pub fn new(original: Frame, corruption: Corruption) -> Self {
+        let mut corrupted = original;
+        let len = corrupted.len;
+        match corruption {
+            Corruption::FlipBit { index, bit } => corrupted.buffer[index % len] ^= 1 << (bit % 8),
+            Corruption::ReplaceByte { index, value } => corrupted.buffer[index % len] = value,
+            Corruption::Truncate(new_len) => corrupted.len = new_len % len,
+            Corruption::InvalidCrc(delta) => {
+                corrupted.buffer[len - 1] = corrupted.buffer[len - 1].wrapping_add(delta.max(1))
+            }
+        }
+
+        Self {
+            original,
+            corruption,
+            corrupted,
+        }
+    }
+
+    /// The frame before it has been corrupted.
+    pub fn original(&self) -> &Frame {
+        &self.original
+    }
+
+    /// The corruption which has been applied.
+    pub fn corruption(&self) -> &Corruption {
+        &self.corruption
+    }
+
+    /// The bytes of the corrupted frame.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.corrupted.as_bytes()
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Frame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(crate::ControllerEvent::arbitrary(u)?.to_frame())
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CorruptedFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(CorruptedFrame::new(
+            Frame::arbitrary(u)?,
+            Corruption::arbitrary(u)?,
+        ))
+    }
+}
+
+/// [proptest](https://docs.rs/proptest) strategies for the events and frames.
+#[cfg(feature = "proptest")]
+pub mod strategies {
+    extern crate alloc;
+
+    #[cfg(feature = "accelerometer_event")]
+    use crate::accelerometer_event::AccelerometerEvent;
+    #[cfg(feature = "button_event")]
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    #[cfg(feature = "color_event")]
+    use crate::color_event::ColorEvent;
+    use crate::fuzzing::{CorruptedFrame, Corruption};
+    #[cfg(feature = "gyro_event")]
+    use crate::gyro_event::GyroEvent;
+    #[cfg(feature = "location_event")]
+    use crate::location_event::LocationEvent;
+    #[cfg(feature = "magnetometer_event")]
+    use crate::magnetometer_event::MagnetometerEvent;
+    #[cfg(feature = "quaternion_event")]
+    use crate::quaternion_event::QuaternionEvent;
+    use crate::{ControllerEvent, Frame};
+    use alloc::vec::Vec;
+    use proptest::prelude::*;
+    use proptest::strategy::Union;
+
+    /// Any [`Button`], mostly the ones sent by the Adafruit app.
+    #[cfg(feature = "button_event")]
+    pub fn button() -> impl Strategy<Value = Button> {
+        prop_oneof![
+            8 => (b'1'..=b'8').prop_map(|id| Button::from_id_lenient(&id)),
+            1 => any::<u8>().prop_map(|id| Button::from_id_lenient(&id)),
+        ]
+    }
+
+    /// Any [`ButtonState`].
+    #[cfg(feature = "button_event")]
+    pub fn button_state() -> impl Strategy<Value = ButtonState> {
+        prop_oneof![Just(ButtonState::Released), Just(ButtonState::Pressed)]
+    }
+
+    /// Any [`ButtonEvent`].
+    #[cfg(feature = "button_event")]
+    pub fn button_event() -> impl Strategy<Value = ButtonEvent> {
+        (button(), button_state()).prop_map(|(button, state)| ButtonEvent::new(button, state))
+    }
+
+    /// Any [`ColorEvent`].
+    #[cfg(feature = "color_event")]
+    pub fn color_event() -> impl Strategy<Value = ColorEvent> {
+        any::<[u8; 3]>().prop_map(|[red, green, blue]| ColorEvent::new(red, green, blue))
+    }
+
+    /// Any [`QuaternionEvent`].
+    #[cfg(feature = "quaternion_event")]
+    pub fn quaternion_event() -> impl Strategy<Value = QuaternionEvent> {
+        any::<[f32; 4]>().prop_map(|[x, y, z, w]| QuaternionEvent::new(x, y, z, w))
+    }
+
+    /// Any [`AccelerometerEvent`].
+    #[cfg(feature = "accelerometer_event")]
+    pub fn accelerometer_event() -> impl Strategy<Value = AccelerometerEvent> {
+        any::<[f32; 3]>().prop_map(|[x, y, z]| AccelerometerEvent::new(x, y, z))
+    }
+
+    /// Any [`GyroEvent`].
+    #[cfg(feature = "gyro_event")]
+    pub fn gyro_event() -> impl Strategy<Value = GyroEvent> {
+        any::<[f32; 3]>().prop_map(|[x, y, z]| GyroEvent::new(x, y, z))
+    }
+
+    /// Any [`MagnetometerEvent`].
+    #[cfg(feature = "magnetometer_event")]
+    pub fn magnetometer_event() -> impl Strategy<Value = MagnetometerEvent> {
+        any::<[f32; 3]>().prop_map(|[x, y, z]| MagnetometerEvent::new(x, y, z))
+    }
+
+    /// Any [`LocationEvent`].
+    #[cfg(feature = "location_event")]
+    pub fn location_event() -> impl Strategy<Value = LocationEvent> {
+        any::<[f32; 3]>().prop_map(|[latitude, longitude, altitude]| {
+            LocationEvent::new(latitude, longitude, altitude)
+        })
+    }
+
+    /// Any [`ControllerEvent`] of the selected event types.
+    #[allow(clippy::vec_init_then_push)] // the events depend on the selected features
+    pub fn controller_event() -> impl Strategy<Value = ControllerEvent> {
+        let mut events: Vec<BoxedStrategy<ControllerEvent>> = Vec::new();
+        #[cfg(feature = "button_event")]
+        events.push(
+            button_event()
+                .prop_map(ControllerEvent::ButtonEvent)
+                .boxed(),
+        );
+        #[cfg(feature = "color_event")]
+        events.push(color_event().prop_map(ControllerEvent::ColorEvent).boxed());
+        #[cfg(feature = "quaternion_event")]
+        events.push(
+            quaternion_event()
+                .prop_map(ControllerEvent::QuaternionEvent)
+                .boxed(),
+        );
+        #[cfg(feature = "accelerometer_event")]
+        events.push(
+            accelerometer_event()
+                .prop_map(ControllerEvent::AccelerometerEvent)
+                .boxed(),
+        );
+        #[cfg(feature = "gyro_event")]
+        events.push(gyro_event().prop_map(ControllerEvent::GyroEvent).boxed());
+        #[cfg(feature = "magnetometer_event")]
+        events.push(
+            magnetometer_event()
+                .prop_map(ControllerEvent::MagnetometerEvent)
+                .boxed(),
+        );
+        #[cfg(feature = "location_event")]
+        events.push(
+            location_event()
+                .prop_map(ControllerEvent::LocationEvent)
+                .boxed(),
+        );
+        Union::new(events)
+    }
+
+    /// The [`Frame`] of any [`ControllerEvent`].
+    pub fn frame() -> impl Strategy<Value = Frame> {
+        controller_event().prop_map(|event| event.to_frame())
+    }
+
+    /// Any [`Corruption`].
+    pub fn corruption() -> impl Strategy<Value = Corruption> {
+        prop_oneof![
+            (any::<usize>(), any::<u8>())
+                .prop_map(|(index, bit)| Corruption::FlipBit { index, bit }),
+            (any::<usize>(), any::<u8>())
+                .prop_map(|(index, value)| Corruption::ReplaceByte { index, value }),
+            any::<usize>().prop_map(Corruption::Truncate),
+            any::<u8>().prop_map(Corruption::InvalidCrc),
+        ]
+    }
+
+    /// The frame of any [`ControllerEvent`] with any [`Corruption`].
+    pub fn corrupted_frame() -> impl Strategy<Value = CorruptedFrame> {
+        (frame(), corruption())
+            .prop_map(|(frame, corruption)| CorruptedFrame::new(frame, corruption))
+    }
+}
+
+#[cfg(all(test, feature = "proptest", feature = "button_event"))]
+mod tests {
+    extern crate std;
+
+    use crate::button_event::ButtonParseMode;
+    use crate::fuzzing::strategies::{controller_event, corrupted_frame};
+    use crate::multi_parser::{ConnectionId, MultiParser};
+    use crate::Parser;
+    use proptest::prelude::*;
+    use std::vec::Vec;
+
+    proptest! {
+        #[test]
+        fn test_frame_round_trip(event in controller_event()) {
+            let frame = event.to_frame();
+            let mut parser = Parser::new(frame.as_bytes()).with_button_parse_mode(ButtonParseMode::Lenient);
+            let parsed = parser.next().unwrap().unwrap();
+            // compare the frames as the floats might be `NaN`
+            prop_assert_eq!(parsed.to_frame(), frame);
+            prop_assert!(parser.next().is_none());
+        }
+
+        #[test]
+        fn test_corrupted_frame_does_not_panic(frame in corrupted_frame()) {
+            let _ = Parser::new(frame.as_bytes()).count();
+        }
+
+        #[test]
+        fn test_random_input_does_not_panic(input in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let _ = Parser::new(&input).count();
+            let mut parser = MultiParser::<1>::new();
+            let _ = parser.feed(ConnectionId(0), &input).unwrap().count();
+        }
+
+        #[test]
+        fn test_concatenated_frames(events in proptest::collection::vec(controller_event(), 0..8)) {
+            let input: Vec<u8> = events.iter().flat_map(|e| e.to_frame().as_bytes().to_vec()).collect();
+            let parsed: Vec<_> = Parser::new(&input)
+                .with_button_parse_mode(ButtonParseMode::Lenient)
+                .map(|e| e.unwrap().to_frame())
+                .collect();
+            let expected: Vec<_> = events.iter().map(|e| e.to_frame()).collect();
+            prop_assert_eq!(parsed, expected);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "arbitrary"))]
+mod arbitrary_tests {
+    use crate::fuzzing::{CorruptedFrame, Corruption};
+    use crate::{ControllerEvent, Frame, Parser};
+    use arbitrary::{Arbitrary, Unstructured};
+
+    #[test]
+    fn test_arbitrary_frame_is_valid() {
+        let data: [u8; 256] = core::array::from_fn(|i| (i * 37 + 11) as u8);
+        let mut u = Unstructured::new(&data);
+        while let Ok(frame) = Frame::arbitrary(&mut u) {
+            if u.is_empty() {
+                break;
+            }
+            let parsed = Parser::new(frame.as_bytes())
+                .next()
+                .unwrap()
+                .map(|e| e.to_frame());
+            #[cfg(feature = "button_event")]
+            let parsed = parsed.or_else(|_| {
+                Parser::new(frame.as_bytes())
+                    .with_button_parse_mode(crate::button_event::ButtonParseMode::Lenient)
+                    .next()
+                    .unwrap()
+                    .map(|e| e.to_frame())
+            });
+            assert_eq!(parsed, Ok(frame));
+        }
+    }
+
+    #[test]
+    fn test_corruption() {
+        let frame = ControllerEvent::arbitrary(&mut Unstructured::new(&[0; 32]))
+            .unwrap()
+            .to_frame();
+        let len = frame.as_bytes().len();
+
+        let corrupted = CorruptedFrame::new(frame, Corruption::Truncate(len + 2));
+        assert_eq!(corrupted.as_bytes(), &frame.as_bytes()[..2]);
+        assert_eq!(corrupted.original(), &frame);
+
+        let corrupted = CorruptedFrame::new(frame, Corruption::InvalidCrc(0));
+        assert_eq!(corrupted.as_bytes()[..len - 1], frame.as_bytes()[..len - 1]);
+        assert_ne!(corrupted.as_bytes()[len - 1], frame.as_bytes()[len - 1]);
+
+        let corrupted = CorruptedFrame::new(frame, Corruption::FlipBit { index: 0, bit: 9 });
+        assert_eq!(corrupted.as_bytes()[0], b'!' ^ 2);
+    }
+}
@@ -0,0 +1,457 @@
+//! Implements the [`GestureRecognizer`] which turns button events into clicks, double clicks, long presses and chords.
+
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::button_states::ButtonSet;
+use super::time::{Clock, Duration, Instant};
+use super::{ControllerEvent, ProtocolParseError};
+
+/// A gesture recognized by the [`GestureRecognizer`].
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Gesture {
+    /// The button has been pressed and released once.
+    Click(Button),
+    /// The button has been pressed twice within the double click interval.
+    DoubleClick(Button),
+    /// The button has been held for at least the long press duration. The parameter is the time it had been held when
+    /// the long press was recognized.
+    LongPress(Button, Duration),
+    /// Several buttons have been held at the same time. Emitted once all of them have been released.
+    Chord(ButtonSet),
+}
+
+/// The state of a single button.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct State {
+    /// When the button has been pressed if it is held.
+    pressed_at: Option<Instant>,
+    /// When the button has been released after a click which might still become a double click.
+    pending_click: Option<Instant>,
+    /// The current press is the second one of a double click, so its release is not a gesture.
+    double_click: bool,
+    /// The current press has already been reported as a long press, so its release is not a gesture.
+    long_press: bool,
+}
+
+/// Recognizes gestures from the button events, so a few buttons can be used for more functions.
+///
+/// * a press and release is a [`Gesture::Click`]. If the double click interval is not zero the click is only emitted
+///   once the interval has elapsed without a second press (either by the next event or by [`GestureRecognizer::poll`]).
+/// * a second press within the double click interval after a click is a [`Gesture::DoubleClick`], it is emitted
+///   right away and instead of the two clicks.
+/// * a press held for at least the long press duration is a [`Gesture::LongPress`]. It is emitted by
+///   [`GestureRecognizer::poll`] once the duration has elapsed while the button is still held (or on the release if
+///   `poll` has not been called in the meantime). The release of a long press is not reported as a click.
+/// * holding several buttons at the same time is a [`Gesture::Chord`] (emitted once all of them have been released).
+///   The buttons of a chord are not reported as clicks or long presses, unless the long press has already been
+///   emitted before the chord was started.
+///
+/// Repeated events (e.g. a press of a button which is already held) and events for [`Button::Other`] are ignored.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::button_event::Button;
+/// # use adafruit_bluefruit_protocol::gestures::{Gesture, GestureRecognizer};
+/// # use adafruit_bluefruit_protocol::time::{Duration, Instant};
+/// # use adafruit_bluefruit_protocol::Parser;
+/// # use core::cell::Cell;
+/// let now = Cell::new(Instant::from_millis(0));
+/// let clock = || now.get();
+/// let mut recognizer = GestureRecognizer::new();
+///
+/// // button 1 is pressed and released twice
+/// let mut gestures = recognizer.recognize(Parser::new(b"!B11:!B10;!B11:!B10;"), &clock);
+/// assert_eq!(gestures.next(), Some(Ok(Gesture::DoubleClick(Button::Button1))));
+/// assert_eq!(gestures.next(), None);
+///
+/// // button 2 is clicked, the click is emitted once the double click interval has elapsed
+/// assert_eq!(recognizer.recognize(Parser::new(b"!B219!B20:"), &clock).count(), 0);
+/// now.set(Instant::from_millis(400));
+/// assert_eq!(recognizer.poll(now.get()), Some(Gesture::Click(Button::Button2)));
+/// ```
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GestureRecognizer {
+    double_click_interval: Duration,
+    long_press_duration: Duration,
+    states: [State; Button::KNOWN.len()],
+    held: ButtonSet,
+    chord: ButtonSet,
+}
+
+impl GestureRecognizer {
+    /// Create a recognizer with a double click interval of 300ms and a long press duration of 500ms.
+    pub const fn new() -> Self {
+        Self {
+            double_click_interval: Duration::from_millis(300),
+            long_press_duration: Duration::from_millis(500),
+            states: [State {
+                pressed_at: None,
+                pending_click: None,
+                double_click: false,
+                long_press: false,
+            }; Button::KNOWN.len()],
+            held: ButtonSet::new(),
+            chord: ButtonSet::new(),
+        }
+    }
+
+    /// Set the time within which the second press must follow a click to be a double click.
+    /// With [`Duration::ZERO`] no double clicks are recognized and clicks are emitted right away.
+    pub const fn with_double_click_interval(mut self, interval: Duration) -> Self {
+        self.double_click_interval = interval;
+        self
+    }
+
+    /// Set how long a button must be held to be a long press.
+    pub const fn with_long_press_duration(mut self, duration: Duration) -> Self {
+        self.long_press_duration = duration;
+        self
+    }
+
+    /// Recognize the gestures in the events, the current time is taken from the clock. All other events are skipped.
+    ///
+    /// At the end of the input all clicks and long presses which are due are emitted as well (as with
+    /// [`GestureRecognizer::poll`]).
+    pub fn recognize<'a, I, C>(
+        &'a mut self,
+        events: I,
+        clock: &'a C,
+    ) -> Gestures<'a, I::IntoIter, C>
+    where
+        I: IntoIterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+        C: Clock,
+    {
+        Gestures {
+            events: events.into_iter(),
+            clock,
+            recognizer: self,
+        }
+    }
+
+    /// Process the button event which has been received at `now`, returns the gesture it completes (if any).
+    pub fn process(&mut self, event: &ButtonEvent, now: Instant) -> Option<Gesture> {
+        let button = *event.button();
+        let index = button.index()?;
+        let double_click_interval = self.double_click_interval;
+        let long_press_duration = self.long_press_duration;
+        let state = &mut self.states[index];
+
+        match event.state() {
+            ButtonState::Pressed => {
+                if state.pressed_at.is_some() {
+                    return None;
+                }
+                state.pressed_at = Some(now);
+                self.held.insert(button);
+                if self.held.len() > 1 {
+                    self.chord = self.chord.iter().chain(self.held).collect();
+                }
+
+                let released_at = state.pending_click.take()?;
+                if now - released_at <= double_click_interval {
+                    state.double_click = true;
+                    Some(Gesture::DoubleClick(button))
+                } else {
+                    Some(Gesture::Click(button))
+                }
+            }
+            ButtonState::Released => {
+                let held_for = now - state.pressed_at.take()?;
+                self.held.remove(button);
+                let long_press = core::mem::take(&mut state.long_press);
+                if !self.chord.is_empty() {
+                    state.double_click = false;
+                    return if self.held.is_empty() {
+                        let chord = self.chord;
+                        self.chord = ButtonSet::new();
+                        Some(Gesture::Chord(chord))
+                    } else {
+                        None
+                    };
+                }
+
+                if core::mem::take(&mut state.double_click) || long_press {
+                    None
+                } else if held_for >= long_press_duration {
+                    Some(Gesture::LongPress(button, held_for))
+                } else if double_click_interval == Duration::ZERO {
+                    Some(Gesture::Click(button))
+                } else {
+                    state.pending_click = Some(now);
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns the next click whose double click interval has elapsed by now or the next button which has been held
+    /// for the long press duration by now.
+    ///
+    /// Call this regularly (until it returns `None`) if no new input arrives, otherwise clicks and long presses are
+    /// delivered late.
+    pub fn poll(&mut self, now: Instant) -> Option<Gesture> {
+        let double_click_interval = self.double_click_interval;
+        let long_press_duration = self.long_press_duration;
+        let chord = self.chord;
+        Button::KNOWN
+            .iter()
+            .zip(self.states.iter_mut())
+            .find_map(|(button, state)| {
+                if let Some(released_at) = state.pending_click {
+                    if now - released_at > double_click_interval {
+                        state.pending_click = None;
+                        return Some(Gesture::Click(*button));
+                    }
+                }
+                let pressed_at = state.pressed_at?;
+                let held_for = now - pressed_at;
+                if held_for >= long_press_duration
+                    && !state.long_press
+                    && !state.double_click
+                    && chord.is_empty()
+                {
+                    state.long_press = true;
+                    Some(Gesture::LongPress(*button, held_for))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Forget all held buttons and pending clicks, e.g. after the connection has been lost.
+    pub fn reset(&mut self) {
+        *self = Self {
+            double_click_interval: self.double_click_interval,
+            long_press_duration: self.long_press_duration,
+            ..Self::new()
+        };
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator adaptor which recognizes the gestures, see [`GestureRecognizer::recognize`].
+#[derive(Debug)]
+pub struct Gestures<'a, I, C> {
+    events: I,
+    clock: &'a C,
+    recognizer: &'a mut GestureRecognizer,
+}
+
+impl<I, C> Iterator for Gestures<'_, I, C>
+where
+    I: Iterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+    C: Clock,
+{
+    type Item = Result<Gesture, ProtocolParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for event in self.events.by_ref() {
+            match event {
+                #[allow(irrefutable_let_patterns)] // if only `button_event` has been selected
+                Ok(event) => {
+                    if let ControllerEvent::ButtonEvent(event) = event {
+                        if let Some(gesture) = self.recognizer.process(&event, self.clock.now()) {
+                            return Some(Ok(gesture));
+                        }
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        self.recognizer.poll(self.clock.now()).map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::button_states::ButtonSet;
+    use crate::gestures::{Gesture, GestureRecognizer};
+    use crate::time::{Duration, Instant};
+
+    fn press(recognizer: &mut GestureRecognizer, button: Button, millis: u64) -> Option<Gesture> {
+        recognizer.process(
+            &ButtonEvent::new(button, ButtonState::Pressed),
+            Instant::from_millis(millis),
+        )
+    }
+
+    fn release(recognizer: &mut GestureRecognizer, button: Button, millis: u64) -> Option<Gesture> {
+        recognizer.process(
+            &ButtonEvent::new(button, ButtonState::Released),
+            Instant::from_millis(millis),
+        )
+    }
+
+    #[test]
+    fn test_click_without_double_click() {
+        let mut recognizer = GestureRecognizer::new().with_double_click_interval(Duration::ZERO);
+
+        assert_eq!(press(&mut recognizer, Button::Up, 0), None);
+        assert_eq!(
+            release(&mut recognizer, Button::Up, 100),
+            Some(Gesture::Click(Button::Up))
+        );
+        assert_eq!(press(&mut recognizer, Button::Up, 150), None);
+        assert_eq!(
+            release(&mut recognizer, Button::Up, 200),
+            Some(Gesture::Click(Button::Up))
+        );
+    }
+
+    #[test]
+    fn test_click_is_emitted_after_double_click_interval() {
+        let mut recognizer = GestureRecognizer::new();
+
+        assert_eq!(press(&mut recognizer, Button::Button3, 0), None);
+        assert_eq!(release(&mut recognizer, Button::Button3, 100), None);
+        assert_eq!(recognizer.poll(Instant::from_millis(400)), None);
+        assert_eq!(
+            recognizer.poll(Instant::from_millis(401)),
+            Some(Gesture::Click(Button::Button3))
+        );
+        assert_eq!(recognizer.poll(Instant::from_millis(1000)), None);
+    }
+
+    #[test]
+    fn test_late_second_press_emits_first_click() {
+        let mut recognizer = GestureRecognizer::new();
+
+        press(&mut recognizer, Button::Button3, 0);
+        release(&mut recognizer, Button::Button3, 100);
+        assert_eq!(
+            press(&mut recognizer, Button::Button3, 500),
+            Some(Gesture::Click(Button::Button3))
+        );
+        assert_eq!(release(&mut recognizer, Button::Button3, 550), None);
+    }
+
+    #[test]
+    fn test_double_click() {
+        let mut recognizer = GestureRecognizer::new();
+
+        press(&mut recognizer, Button::Left, 0);
+        release(&mut recognizer, Button::Left, 50);
+        assert_eq!(
+            press(&mut recognizer, Button::Left, 200),
+            Some(Gesture::DoubleClick(Button::Left))
+        );
+        assert_eq!(release(&mut recognizer, Button::Left, 250), None);
+        assert_eq!(recognizer.poll(Instant::from_millis(1000)), None);
+    }
+
+    #[test]
+    fn test_long_press() {
+        let mut recognizer =
+            GestureRecognizer::new().with_long_press_duration(Duration::from_millis(1000));
+
+        press(&mut recognizer, Button::Button1, 0);
+        assert_eq!(
+            release(&mut recognizer, Button::Button1, 1500),
+            Some(Gesture::LongPress(
+                Button::Button1,
+                Duration::from_millis(1500)
+            ))
+        );
+        assert_eq!(recognizer.poll(Instant::from_millis(5000)), None);
+    }
+
+    #[test]
+    fn test_long_press_while_held() {
+        let mut recognizer = GestureRecognizer::new();
+
+        press(&mut recognizer, Button::Button2, 0);
+        assert_eq!(recognizer.poll(Instant::from_millis(499)), None);
+        assert_eq!(
+            recognizer.poll(Instant::from_millis(520)),
+            Some(Gesture::LongPress(
+                Button::Button2,
+                Duration::from_millis(520)
+            ))
+        );
+        // only emitted once and the release is not a click
+        assert_eq!(recognizer.poll(Instant::from_millis(800)), None);
+        assert_eq!(release(&mut recognizer, Button::Button2, 900), None);
+        assert_eq!(recognizer.poll(Instant::from_millis(2000)), None);
+
+        // the next press starts over
+        press(&mut recognizer, Button::Button2, 3000);
+        assert_eq!(release(&mut recognizer, Button::Button2, 3100), None);
+        assert_eq!(
+            recognizer.poll(Instant::from_millis(3500)),
+            Some(Gesture::Click(Button::Button2))
+        );
+    }
+
+    #[test]
+    fn test_no_long_press_while_held_in_chord_or_double_click() {
+        let mut recognizer = GestureRecognizer::new();
+
+        press(&mut recognizer, Button::Button1, 0);
+        press(&mut recognizer, Button::Button2, 20);
+        assert_eq!(recognizer.poll(Instant::from_millis(1000)), None);
+        release(&mut recognizer, Button::Button1, 1100);
+        release(&mut recognizer, Button::Button2, 1100);
+
+        press(&mut recognizer, Button::Up, 2000);
+        release(&mut recognizer, Button::Up, 2050);
+        press(&mut recognizer, Button::Up, 2100);
+        assert_eq!(recognizer.poll(Instant::from_millis(3000)), None);
+        assert_eq!(release(&mut recognizer, Button::Up, 3100), None);
+    }
+
+    #[test]
+    fn test_chord() {
+        let mut recognizer = GestureRecognizer::new();
+
+        press(&mut recognizer, Button::Button1, 0);
+        press(&mut recognizer, Button::Button2, 20);
+        assert_eq!(release(&mut recognizer, Button::Button1, 100), None);
+        press(&mut recognizer, Button::Button4, 120);
+        assert_eq!(release(&mut recognizer, Button::Button2, 2000), None);
+        assert_eq!(
+            release(&mut recognizer, Button::Button4, 2100),
+            Some(Gesture::Chord(
+                ButtonSet::new()
+                    .with(Button::Button1)
+                    .with(Button::Button2)
+                    .with(Button::Button4)
+            ))
+        );
+        assert_eq!(recognizer.poll(Instant::from_millis(5000)), None);
+    }
+
+    #[test]
+    fn test_duplicate_events_are_ignored() {
+        let mut recognizer = GestureRecognizer::new();
+
+        assert_eq!(release(&mut recognizer, Button::Down, 0), None);
+        press(&mut recognizer, Button::Down, 10);
+        assert_eq!(press(&mut recognizer, Button::Down, 600), None);
+        assert_eq!(
+            release(&mut recognizer, Button::Down, 700),
+            Some(Gesture::LongPress(Button::Down, Duration::from_millis(690)))
+        );
+        assert_eq!(press(&mut recognizer, Button::Other(b'9'), 800), None);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut recognizer = GestureRecognizer::new();
+
+        press(&mut recognizer, Button::Down, 0);
+        recognizer.reset();
+        assert_eq!(release(&mut recognizer, Button::Down, 100), None);
+        assert_eq!(recognizer, GestureRecognizer::new());
+    }
+}
@@ -0,0 +1,420 @@
+//! Implements the [`HidGamepad`] which turns the buttons and the tilt of the phone into the [`GamepadReport`] of a
+//! USB HID gamepad.
+
+#[cfg(feature = "accelerometer_event")]
+use super::accelerometer_event::AccelerometerEvent;
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::button_states::ButtonSet;
+use super::dpad::Direction;
+#[cfg(feature = "quaternion_event")]
+use super::quaternion_event::QuaternionEvent;
+use super::{abs_f32, ControllerEvent};
+pub use report::GamepadReport;
+
+#[allow(missing_docs)] // the constant with the descriptor generated by the macro is not documented
+mod report {
+    use usbd_hid::descriptor::generator_prelude::*;
+
+    /// The input report of a gamepad with four buttons, a hat switch and two axes.
+    ///
+    /// Use `GamepadReport::desc()` (from `usbd_hid::descriptor::SerializedDescriptor`) as the report descriptor of the
+    /// HID class and send the reports e.g. with `usbd_hid::hid_class::HIDClass::push_input`.
+    #[gen_hid_descriptor(
+        (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = GAMEPAD) = {
+            (usage_page = BUTTON, usage_min = BUTTON_1, usage_max = BUTTON_4) = {
+                #[packed_bits = 4] #[item_settings(data,variable,absolute)] buttons=input;
+            };
+            (usage_page = GENERIC_DESKTOP,) = {
+                (usage = 0x39, logical_min = 0, logical_max = 7) = {
+                    #[item_settings(data,variable,absolute,null)] hat=input;
+                };
+            };
+            (usage_page = GENERIC_DESKTOP,) = {
+                (usage = X,) = {
+                    #[item_settings(data,variable,absolute)] x=input;
+                };
+                (usage = Y,) = {
+                    #[item_settings(data,variable,absolute)] y=input;
+                };
+            };
+        }
+    )]
+    pub struct GamepadReport {
+        /// The number buttons as bits, [`Button::Button1`](crate::button_event::Button::Button1) is the lowest bit.
+        pub buttons: u8,
+        /// The direction of the arrows: `0` for up, counting clockwise up to `7` for up left,
+        /// [`GamepadReport::HAT_CENTER`] if no arrow is held.
+        pub hat: u8,
+        /// The tilt to the right (positive) or left (negative).
+        pub x: i8,
+        /// The tilt towards (positive) or away from (negative) the user.
+        pub y: i8,
+    }
+}
+
+impl GamepadReport {
+    /// The value of [`GamepadReport::hat`] if no arrow is held (a null value outside the logical range).
+    pub const HAT_CENTER: u8 = 8;
+
+    /// The hat switch value for the direction.
+    pub const fn hat_from_direction(direction: Direction) -> u8 {
+        match direction {
+            Direction::Up => 0,
+            Direction::UpRight => 1,
+            Direction::Right => 2,
+            Direction::DownRight => 3,
+            Direction::Down => 4,
+            Direction::DownLeft => 5,
+            Direction::Left => 6,
+            Direction::UpLeft => 7,
+            Direction::Center => Self::HAT_CENTER,
+        }
+    }
+}
+
+/// Defines how the tilt is mapped to an axis of the [`GamepadReport`].
+///
+/// The tilt is the component of gravity along the axis of the phone (from -1 to 1 for a quaternion, in the unit sent by
+/// the app for the accelerometer). Tilts within the dead zone around the center result in 0, beyond that the axis
+/// grows linearly until it reaches its full value at the range.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct AxisCalibration {
+    center: f32,
+    range: f32,
+    dead_zone: f32,
+    inverted: bool,
+}
+
+impl AxisCalibration {
+    /// Create a calibration centered at 0 with a range of 0.5 (i.e. a tilt of 30°) and a dead zone of 0.05.
+    pub const fn new() -> Self {
+        Self {
+            center: 0.0,
+            range: 0.5,
+            dead_zone: 0.05,
+            inverted: false,
+        }
+    }
+
+    /// Set the tilt which results in 0, see also [`HidGamepad::calibrate`].
+    pub const fn with_center(mut self, center: f32) -> Self {
+        self.center = center;
+        self
+    }
+
+    /// Set the distance from the center at which the axis reaches its full value.
+    pub const fn with_range(mut self, range: f32) -> Self {
+        self.range = range;
+        self
+    }
+
+    /// Set the distance from the center within which the axis stays at 0.
+    pub const fn with_dead_zone(mut self, dead_zone: f32) -> Self {
+        self.dead_zone = dead_zone;
+        self
+    }
+
+    /// Set whether the direction of the axis is inverted.
+    pub const fn with_inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// The value of the axis for the tilt.
+    pub fn map(&self, tilt: f32) -> i8 {
+        let offset = if self.inverted {
+            self.center - tilt
+        } else {
+            tilt - self.center
+        };
+        let distance = abs_f32(offset) - self.dead_zone;
+        if distance.is_nan() || distance <= 0.0 {
+            return 0;
+        }
+        let span = self.range - self.dead_zone;
+        let scaled = if span > 0.0 && distance < span {
+            distance / span
+        } else {
+            1.0
+        };
+        // round to the nearest value, the cast saturates
+        let value = (scaled * i8::MAX as f32 + 0.5) as i8;
+        if offset < 0.0 {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+impl Default for AxisCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maps the controller to a USB HID gamepad.
+///
+/// The number buttons are mapped to the buttons of the gamepad, the arrows to its hat switch and the tilt of the phone
+/// (from [`AccelerometerEvent`]s or [`QuaternionEvent`]s, enable only one of them in the app) to its X & Y axes.
+/// Each axis is mapped with an [`AxisCalibration`].
+///
+/// Send the [`HidGamepad::report`] whenever [`HidGamepad::apply`] returns `true`.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::hid_gamepad::{AxisCalibration, GamepadReport, HidGamepad};
+/// # use adafruit_bluefruit_protocol::Parser;
+/// let mut gamepad = HidGamepad::new().with_y_axis(AxisCalibration::new().with_inverted(true));
+/// // button 2 and up are pressed
+/// for event in Parser::new(b"!B219!B516") {
+///     assert!(gamepad.apply(&event.unwrap()));
+/// }
+///
+/// let report = gamepad.report();
+/// assert_eq!(report.buttons, 0b10);
+/// assert_eq!(report.hat, 0);
+/// assert_eq!((report.x, report.y), (0, 0));
+/// ```
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HidGamepad {
+    x_axis: AxisCalibration,
+    y_axis: AxisCalibration,
+    buttons: ButtonSet,
+    tilt: Option<(f32, f32)>,
+}
+
+impl HidGamepad {
+    /// Create the gamepad with the default calibration of the axes and all inputs released.
+    pub const fn new() -> Self {
+        Self {
+            x_axis: AxisCalibration::new(),
+            y_axis: AxisCalibration::new(),
+            buttons: ButtonSet::new(),
+            tilt: None,
+        }
+    }
+
+    /// Set the calibration of the X axis.
+    pub const fn with_x_axis(mut self, calibration: AxisCalibration) -> Self {
+        self.x_axis = calibration;
+        self
+    }
+
+    /// Set the calibration of the Y axis.
+    pub const fn with_y_axis(mut self, calibration: AxisCalibration) -> Self {
+        self.y_axis = calibration;
+        self
+    }
+
+    /// Update the state with the event, all other events are ignored.
+    ///
+    /// Returns whether the report has changed and should be sent.
+    pub fn apply(&mut self, event: &ControllerEvent) -> bool {
+        match event {
+            ControllerEvent::ButtonEvent(event) => self.apply_button(event),
+            #[cfg(feature = "accelerometer_event")]
+            ControllerEvent::AccelerometerEvent(event) => self.apply_accelerometer(event),
+            #[cfg(feature = "quaternion_event")]
+            ControllerEvent::QuaternionEvent(event) => self.apply_quaternion(event),
+            #[allow(unreachable_patterns)] // depends on the selected events
+            _ => false,
+        }
+    }
+
+    /// Update the state with the button event.
+    ///
+    /// Returns whether the report has changed and should be sent.
+    pub fn apply_button(&mut self, event: &ButtonEvent) -> bool {
+        let before = self.buttons;
+        match event.state() {
+            ButtonState::Pressed => self.buttons.insert(*event.button()),
+            ButtonState::Released => self.buttons.remove(*event.button()),
+        }
+        self.buttons != before
+    }
+
+    /// Update the tilt with the accelerometer event.
+    ///
+    /// Returns whether the report has changed and should be sent.
+    #[cfg(feature = "accelerometer_event")]
+    pub fn apply_accelerometer(&mut self, event: &AccelerometerEvent) -> bool {
+        self.set_tilt(event.x(), event.y())
+    }
+
+    /// Update the tilt with the quaternion event, it is converted to the direction of gravity.
+    ///
+    /// Returns whether the report has changed and should be sent.
+    #[cfg(feature = "quaternion_event")]
+    pub fn apply_quaternion(&mut self, event: &QuaternionEvent) -> bool {
+        let (x, y, z, w) = (event.x(), event.y(), event.z(), event.w());
+        self.set_tilt(2.0 * (x * z - w * y), 2.0 * (y * z + w * x))
+    }
+
+    #[cfg(any(feature = "accelerometer_event", feature = "quaternion_event"))]
+    fn set_tilt(&mut self, x: f32, y: f32) -> bool {
+        let before = self.report();
+        self.tilt = Some((x, y));
+        self.report() != before
+    }
+
+    /// Use the current tilt as the center of both axes, e.g. while the user holds the phone in the neutral position.
+    ///
+    /// Returns `false` if no tilt has been received yet.
+    pub fn calibrate(&mut self) -> bool {
+        let Some((x, y)) = self.tilt else {
+            return false;
+        };
+        self.x_axis.center = x;
+        self.y_axis.center = y;
+        true
+    }
+
+    /// Release all inputs and center the axes, e.g. when the connection has been lost. Returns whether the report has
+    /// changed.
+    pub fn release_all(&mut self) -> bool {
+        let before = self.report();
+        self.buttons = ButtonSet::new();
+        self.tilt = None;
+        self.report() != before
+    }
+
+    /// The latest tilt `(x, y)`, `None` if no sensor event has been received yet.
+    pub fn tilt(&self) -> Option<(f32, f32)> {
+        self.tilt
+    }
+
+    /// The report for the current state.
+    pub fn report(&self) -> GamepadReport {
+        let buttons = Button::KNOWN[..4]
+            .iter()
+            .enumerate()
+            .filter(|(_, button)| self.buttons.contains(**button))
+            .fold(0, |bits, (index, _)| bits | 1 << index);
+        let direction = Direction::from_arrows(
+            self.buttons.contains(Button::Up),
+            self.buttons.contains(Button::Down),
+            self.buttons.contains(Button::Left),
+            self.buttons.contains(Button::Right),
+        );
+        let (x, y) = self
+            .tilt
+            .map_or((0, 0), |(x, y)| (self.x_axis.map(x), self.y_axis.map(y)));
+        GamepadReport {
+            buttons,
+            hat: GamepadReport::hat_from_direction(direction),
+            x,
+            y,
+        }
+    }
+}
+
+impl Default for HidGamepad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::hid_gamepad::{AxisCalibration, GamepadReport, HidGamepad};
+    use usbd_hid::descriptor::{AsInputReport, SerializedDescriptor};
+
+    fn press(gamepad: &mut HidGamepad, button: Button) -> bool {
+        gamepad.apply_button(&ButtonEvent::new(button, ButtonState::Pressed))
+    }
+
+    fn report_bytes(gamepad: &HidGamepad) -> [u8; 4] {
+        let mut buffer = [0xff; 4];
+        assert_eq!(gamepad.report().serialize(&mut buffer).ok(), Some(4));
+        buffer
+    }
+
+    #[test]
+    fn test_descriptor() {
+        assert_eq!(
+            GamepadReport::desc(),
+            &[
+                0x05, 0x01, 0x09, 0x05, 0xa1, 0x01, // gamepad collection
+                0x05, 0x09, 0x19, 0x01, 0x29, 0x04, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x04,
+                0x81, 0x02, 0x81, 0x03, // 4 buttons & padding
+                0x05, 0x01, 0x09, 0x39, 0x15, 0x00, 0x25, 0x07, 0x75, 0x08, 0x95, 0x01, 0x81,
+                0x42, // hat switch with null state
+                0x05, 0x01, 0x09, 0x30, 0x17, 0x81, 0xff, 0xff, 0xff, 0x25, 0x7f, 0x81,
+                0x02, // x
+                0x09, 0x31, 0x81, 0x02, // y
+                0xc0,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_buttons_and_hat() {
+        let mut gamepad = HidGamepad::new();
+        assert_eq!(report_bytes(&gamepad), [0, 8, 0, 0]);
+
+        assert!(press(&mut gamepad, Button::Button1));
+        assert!(press(&mut gamepad, Button::Button4));
+        assert!(press(&mut gamepad, Button::Down));
+        assert!(press(&mut gamepad, Button::Left));
+        assert!(!press(&mut gamepad, Button::Left));
+        assert_eq!(report_bytes(&gamepad), [0b1001, 5, 0, 0]);
+
+        assert!(gamepad.release_all());
+        assert_eq!(report_bytes(&gamepad), [0, 8, 0, 0]);
+        assert!(!gamepad.release_all());
+    }
+
+    #[test]
+    fn test_axis_calibration() {
+        let axis = AxisCalibration::new()
+            .with_center(0.1)
+            .with_range(0.6)
+            .with_dead_zone(0.1);
+
+        assert_eq!(axis.map(0.15), 0);
+        assert_eq!(axis.map(0.45), 64);
+        assert_eq!(axis.map(-0.25), -64);
+        assert_eq!(axis.map(0.7), 127);
+        assert_eq!(axis.map(-5.0), -127);
+        assert_eq!(axis.map(f32::NAN), 0);
+        assert_eq!(axis.with_inverted(true).map(0.45), -64);
+    }
+
+    #[test]
+    #[cfg(feature = "accelerometer_event")]
+    fn test_accelerometer() {
+        use crate::accelerometer_event::AccelerometerEvent;
+        use crate::ControllerEvent;
+
+        let mut gamepad = HidGamepad::new().with_y_axis(AxisCalibration::new().with_inverted(true));
+        let event = ControllerEvent::AccelerometerEvent(AccelerometerEvent::new(0.1, 0.2, 0.9));
+        assert!(gamepad.apply(&event));
+        assert!(!gamepad.apply(&event));
+        assert_eq!(report_bytes(&gamepad), [0, 8, 0x0e, 0xd6]);
+
+        assert!(gamepad.calibrate());
+        assert_eq!(report_bytes(&gamepad), [0, 8, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "quaternion_event")]
+    fn test_quaternion() {
+        use crate::abs_f32;
+        use crate::quaternion_event::QuaternionEvent;
+
+        let mut gamepad = HidGamepad::new();
+        assert!(!gamepad.calibrate());
+        // rotated by 30° around the y axis
+        gamepad.apply_quaternion(&QuaternionEvent::new(0.0, 0.258819, 0.0, 0.9659258));
+        let (x, y) = gamepad.tilt().unwrap();
+        assert!(abs_f32(x + 0.5) < 1e-6);
+        assert_eq!(y, 0.0);
+        assert_eq!(report_bytes(&gamepad), [0, 8, 0x81, 0]);
+    }
+}
@@ -0,0 +1,248 @@
+//! Implements the [`HidKeyboard`] which turns the buttons into the [`KeyboardReport`] of a USB HID keyboard.
+
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::button_states::ButtonSet;
+use super::ControllerEvent;
+use usbd_hid::descriptor::{KeyboardReport, KeyboardUsage};
+
+/// The first modifier key (left control), the modifiers up to right GUI are reported as bits instead of key codes.
+const FIRST_MODIFIER: u8 = KeyboardUsage::KeyboardLeftControl as u8;
+/// The last modifier key (right GUI).
+const LAST_MODIFIER: u8 = KeyboardUsage::KeyboardRightGUI as u8;
+/// The number of key codes in a [`KeyboardReport`].
+const MAX_KEYS: usize = 6;
+
+/// Maps the buttons to keys of a USB HID keyboard and keeps track of the held keys.
+///
+/// By default the arrows are mapped to the arrow keys and the number buttons to the keys `1` to `4`, each mapping can
+/// be changed with [`HidKeyboard::with_key`]. Modifier keys (e.g. [`KeyboardUsage::KeyboardLeftShift`]) are reported
+/// in the modifier byte, all other keys in the key codes in the order of [`Button`]. A key which is mapped to several
+/// buttons is reported once while any of them is held. If more than six keys are held the report signals a rollover
+/// error as required by the HID specification.
+///
+/// Send the [`HidKeyboard::report`] (e.g. with `usbd_hid::hid_class::HIDClass::push_input`) whenever
+/// [`HidKeyboard::apply`] returns `true`.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::button_event::Button;
+/// # use adafruit_bluefruit_protocol::hid_keyboard::HidKeyboard;
+/// # use adafruit_bluefruit_protocol::Parser;
+/// # use usbd_hid::descriptor::KeyboardUsage;
+/// let mut keyboard = HidKeyboard::new()
+///     .with_key(Button::Button1, KeyboardUsage::KeyboardSpacebar)
+///     .with_key(Button::Button2, KeyboardUsage::KeyboardLeftShift);
+/// // button 1 and up are pressed
+/// for event in Parser::new(b"!B11:!B516") {
+///     assert!(keyboard.apply(&event.unwrap()));
+/// }
+///
+/// let report = keyboard.report();
+/// assert_eq!(report.modifier, 0);
+/// assert_eq!(report.keycodes, [0x2c, 0x52, 0, 0, 0, 0]);
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HidKeyboard {
+    /// The key usage per button (in the order of [`Button::KNOWN`]), `0` if the button is not mapped.
+    keys: [u8; Button::KNOWN.len()],
+    held: ButtonSet,
+}
+
+impl HidKeyboard {
+    /// Create the keyboard with the default mapping and all keys released.
+    pub const fn new() -> Self {
+        Self {
+            keys: [
+                KeyboardUsage::Keyboard1Exclamation as u8,
+                KeyboardUsage::Keyboard2At as u8,
+                KeyboardUsage::Keyboard3Hash as u8,
+                KeyboardUsage::Keyboard4Dollar as u8,
+                KeyboardUsage::KeyboardUpArrow as u8,
+                KeyboardUsage::KeyboardDownArrow as u8,
+                KeyboardUsage::KeyboardLeftArrow as u8,
+                KeyboardUsage::KeyboardRightArrow as u8,
+            ],
+            held: ButtonSet::new(),
+        }
+    }
+
+    /// Map the button to the key. [`Button::Other`] can not be mapped, it is ignored.
+    pub const fn with_key(mut self, button: Button, key: KeyboardUsage) -> Self {
+        if let Some(index) = button.index() {
+            self.keys[index] = key as u8;
+        }
+        self
+    }
+
+    /// Remove the mapping of the button, it is ignored afterwards.
+    pub const fn without_key(mut self, button: Button) -> Self {
+        if let Some(index) = button.index() {
+            self.keys[index] = 0;
+        }
+        self
+    }
+
+    /// The key to which the button is mapped.
+    pub fn key(&self, button: Button) -> Option<KeyboardUsage> {
+        button
+            .index()
+            .map(|index| self.keys[index])
+            .filter(|key| *key != 0)
+            .map(KeyboardUsage::from)
+    }
+
+    /// Update the held keys with the event, all other events are ignored.
+    ///
+    /// Returns whether the report has changed and should be sent.
+    pub fn apply(&mut self, event: &ControllerEvent) -> bool {
+        #[allow(irrefutable_let_patterns)] // if only `button_event` has been selected
+        if let ControllerEvent::ButtonEvent(event) = event {
+            self.apply_button(event)
+        } else {
+            false
+        }
+    }
+
+    /// Update the held keys with the button event.
+    ///
+    /// Returns whether the report has changed and should be sent.
+    pub fn apply_button(&mut self, event: &ButtonEvent) -> bool {
+        let button = *event.button();
+        if self.key(button).is_none() {
+            return false;
+        }
+        let before = self.report();
+        match event.state() {
+            ButtonState::Pressed => self.held.insert(button),
+            ButtonState::Released => self.held.remove(button),
+        }
+        self.report() != before
+    }
+
+    /// Release all keys, e.g. when the connection has been lost. Returns whether the report has changed.
+    pub fn release_all(&mut self) -> bool {
+        let changed = !self.held.is_empty();
+        self.held = ButtonSet::new();
+        changed
+    }
+
+    /// The report for the currently held keys.
+    pub fn report(&self) -> KeyboardReport {
+        let mut report = KeyboardReport::default();
+        let mut len = 0;
+        for button in self.held {
+            let key = self.key(button).map_or(0, |key| key as u8);
+            if (FIRST_MODIFIER..=LAST_MODIFIER).contains(&key) {
+                report.modifier |= 1 << (key - FIRST_MODIFIER);
+            } else if key != 0 && !report.keycodes[..len.min(MAX_KEYS)].contains(&key) {
+                if len < MAX_KEYS {
+                    report.keycodes[len] = key;
+                }
+                len += 1;
+            }
+        }
+        if len > MAX_KEYS {
+            report.keycodes = [KeyboardUsage::KeyboardErrorRollOver as u8; MAX_KEYS];
+        }
+        report
+    }
+}
+
+impl Default for HidKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::hid_keyboard::HidKeyboard;
+    use usbd_hid::descriptor::{AsInputReport, KeyboardUsage};
+
+    fn press(keyboard: &mut HidKeyboard, button: Button) -> bool {
+        keyboard.apply_button(&ButtonEvent::new(button, ButtonState::Pressed))
+    }
+
+    fn release(keyboard: &mut HidKeyboard, button: Button) -> bool {
+        keyboard.apply_button(&ButtonEvent::new(button, ButtonState::Released))
+    }
+
+    fn report_bytes(keyboard: &HidKeyboard) -> [u8; 8] {
+        let mut buffer = [0xff; 8];
+        assert_eq!(keyboard.report().serialize(&mut buffer).ok(), Some(8));
+        buffer
+    }
+
+    #[test]
+    fn test_default_mapping() {
+        let mut keyboard = HidKeyboard::new();
+        assert_eq!(report_bytes(&keyboard), [0, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert!(press(&mut keyboard, Button::Right));
+        assert!(press(&mut keyboard, Button::Button3));
+        assert_eq!(report_bytes(&keyboard), [0, 0, 0x20, 0x4f, 0, 0, 0, 0]);
+
+        assert!(release(&mut keyboard, Button::Button3));
+        assert_eq!(report_bytes(&keyboard), [0, 0, 0x4f, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let mut keyboard = HidKeyboard::new()
+            .with_key(Button::Button1, KeyboardUsage::KeyboardLeftShift)
+            .with_key(Button::Button2, KeyboardUsage::KeyboardRightGUI);
+        press(&mut keyboard, Button::Button1);
+        press(&mut keyboard, Button::Button2);
+        press(&mut keyboard, Button::Up);
+
+        assert_eq!(report_bytes(&keyboard), [0x82, 0, 0x52, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_unchanged_report() {
+        let mut keyboard = HidKeyboard::new()
+            .without_key(Button::Button4)
+            .with_key(Button::Button2, KeyboardUsage::Keyboard1Exclamation);
+        assert!(press(&mut keyboard, Button::Button1));
+        // same key as button 1
+        assert!(!press(&mut keyboard, Button::Button2));
+        // already held
+        assert!(!press(&mut keyboard, Button::Button1));
+        // not mapped
+        assert!(!press(&mut keyboard, Button::Button4));
+        assert!(!press(&mut keyboard, Button::Other(b'9')));
+        assert_eq!(keyboard.key(Button::Button4), None);
+        assert_eq!(report_bytes(&keyboard), [0, 0, 0x1e, 0, 0, 0, 0, 0]);
+
+        assert!(!release(&mut keyboard, Button::Button1));
+        assert!(release(&mut keyboard, Button::Button2));
+        assert_eq!(report_bytes(&keyboard), [0; 8]);
+    }
+
+    #[test]
+    fn test_rollover() {
+        let mut keyboard = HidKeyboard::new();
+        for button in Button::KNOWN.iter().take(6) {
+            press(&mut keyboard, *button);
+        }
+        assert_eq!(
+            report_bytes(&keyboard),
+            [0, 0, 0x1e, 0x1f, 0x20, 0x21, 0x52, 0x51]
+        );
+
+        assert!(press(&mut keyboard, Button::Left));
+        assert_eq!(report_bytes(&keyboard), [0, 0, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_release_all() {
+        let mut keyboard = HidKeyboard::new();
+        assert!(!keyboard.release_all());
+        press(&mut keyboard, Button::Down);
+
+        assert!(keyboard.release_all());
+        assert_eq!(report_bytes(&keyboard), [0; 8]);
+    }
+}
@@ -5,9 +5,30 @@
 //! crate, i.e. you have to talk to the Adafruit device, the parser just expects a byte sequence.
 //!
 //! ## Optional features
+//! * `fmt` (enabled by default): implements [`Display`](core::fmt::Display) and [`Error`](core::error::Error) for the error types.
+//!   Disable it on very small targets to save the flash needed for the string formatting.
 //! * `defmt`: you can enable the `defmt` feature to get a `defmt::Format` implementation for all structs & enums and a `defmt::debug!` call for each command being parsed.
+//! * `approx`: implements `AbsDiffEq`, `RelativeEq` and `UlpsEq` of the [approx crate](https://crates.io/crates/approx)
+//!   for [`ControllerEvent`] and all events with floats to compare them with a tolerance.
+//! * `arbitrary`: implements `Arbitrary` of the [arbitrary crate](https://crates.io/crates/arbitrary) for all events and frames, see [`fuzzing`].
+//! * `authentication`: adds the [`authentication`] module to verify messages signed with a shared key.
+//!   Note that this is an extension of the protocol which is not supported by the Adafruit app.
+//! * `event_queue`: adds the [`event_queue`] module with a lock-free queue to hand over events from an interrupt handler to a task.
+//! * `fugit`: if enabled, [`time::Instant`] and [`time::Duration`] can be converted from & to the types of the [fugit crate](https://crates.io/crates/fugit).
+//! * `midi`: adds the [`midi`] module to use the controller as a simple MIDI controller: the buttons play notes and
+//!   the color picker & the orientation of the phone send control changes (as raw 3-byte MIDI messages).
+//! * `proptest`: adds [proptest](https://crates.io/crates/proptest) strategies for all events and frames, see `fuzzing::strategies`.
 //! * `rgb`: if enabled, `From<ColorEvent> for RGB8` is implemented to support the [RGB crate](https://crates.io/crates/rgb).
-//! * `serde`: if enabled, all events implement the [serde](https://serde.rs/) `#[derive(Serialize, Deserialize)]`.
+//! * `schemars`: if enabled, all types which support `serde` also implement `JsonSchema` of the [schemars crate](https://crates.io/crates/schemars).
+//!   This implies the `serde` feature.
+//! * `serde`: if enabled, all events, errors and other data types implement the [serde](https://serde.rs/) `#[derive(Serialize, Deserialize)]`.
+//!   The representation uses the names of the types, fields and variants and is kept stable, see [`ControllerEvent`]
+//!   for how the events are tagged. Stateful helpers (e.g. the parsers) are not serializable.
+//! * `test-utils`: adds the [`test_utils`] module to build valid and malformed messages (with the expected parser result)
+//!   for your tests. It needs an allocator.
+//! * `usbd-hid`: adds the [`hid_keyboard`] and [`hid_gamepad`] modules to map the controller to the reports of a USB HID
+//!   keyboard or gamepad using the [usbd-hid crate](https://crates.io/crates/usbd-hid), e.g. for adapters which make
+//!   the control pad look like a keyboard.
 //! * All events can be selected as individual features. By default, they are all selected,
 //!   but you can opt to only select the event(s) you are interested in which will result in a small binary size.
 //!   If other events are received, a [`ProtocolParseError::DisabledControllerDataPackageType`] will be returned.
@@ -21,6 +42,10 @@
 #![deny(unused)]
 #![no_std]
 
+// the derive of `arbitrary::Arbitrary` needs `std`, the `arbitrary` crate itself depends on it anyway
+#[cfg(feature = "arbitrary")]
+extern crate std;
+
 #[cfg(not(any(
     feature = "accelerometer_event",
     feature = "button_event",
@@ -34,30 +59,71 @@ compile_error!("at least one event type must be selected in the features!");
 
 #[cfg(feature = "accelerometer_event")]
 pub mod accelerometer_event;
+#[cfg(feature = "approx")]
+mod approx_eq;
+#[cfg(feature = "authentication")]
+pub mod authentication;
+#[cfg(feature = "button_event")]
+pub mod auto_repeat;
+#[cfg(feature = "button_event")]
+pub mod bindings;
 #[cfg(feature = "button_event")]
 pub mod button_event;
+#[cfg(feature = "button_event")]
+pub mod button_states;
 #[cfg(feature = "color_event")]
 pub mod color_event;
+#[cfg(feature = "button_event")]
+pub mod differential_drive;
+#[cfg(feature = "button_event")]
+pub mod dpad;
+pub mod event_handler;
+#[cfg(feature = "event_queue")]
+pub mod event_queue;
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub mod fuzzing;
+#[cfg(feature = "button_event")]
+pub mod gestures;
 #[cfg(feature = "gyro_event")]
 pub mod gyro_event;
+#[cfg(all(feature = "usbd-hid", feature = "button_event"))]
+pub mod hid_gamepad;
+#[cfg(all(feature = "usbd-hid", feature = "button_event"))]
+pub mod hid_keyboard;
 #[cfg(feature = "location_event")]
 pub mod location_event;
 #[cfg(feature = "magnetometer_event")]
 pub mod magnetometer_event;
+#[cfg(all(feature = "midi", feature = "button_event"))]
+pub mod midi;
+pub mod multi_parser;
 #[cfg(feature = "quaternion_event")]
 pub mod quaternion_event;
+pub mod snapshot;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod text;
+pub mod throttle;
+pub mod time;
+pub mod timestamp;
+pub mod validation;
 
 #[cfg(feature = "accelerometer_event")]
 use accelerometer_event::AccelerometerEvent;
+#[cfg(feature = "authentication")]
+use authentication::AuthenticationError;
 #[cfg(feature = "button_event")]
-use button_event::{ButtonEvent, ButtonParseError};
+use button_event::{ButtonEvent, ButtonParseError, ButtonParseMode};
 #[cfg(feature = "color_event")]
 use color_event::ColorEvent;
 use core::cmp::min;
+use core::str::FromStr;
 #[cfg(feature = "gyro_event")]
 use gyro_event::GyroEvent;
 
+#[cfg(feature = "fmt")]
 use core::error::Error;
+#[cfg(feature = "fmt")]
 use core::fmt::{Display, Formatter};
 #[cfg(feature = "location_event")]
 use location_event::LocationEvent;
@@ -65,10 +131,19 @@ use location_event::LocationEvent;
 use magnetometer_event::MagnetometerEvent;
 #[cfg(feature = "quaternion_event")]
 use quaternion_event::QuaternionEvent;
+use text::{TextParseError, Tokens};
+use validation::{Validation, ValidationError};
 
 /// Lists all (supported) events which can be sent by the controller. These come with the parsed event data.
+///
+/// With the `serde` feature the event is tagged with the name of the variant,
+/// e.g. `{"ColorEvent":{"red":255,"green":45,"blue":57}}` in JSON. This representation also works for formats which are
+/// not self-describing (e.g. [postcard](https://crates.io/crates/postcard)).
 #[derive(PartialEq, Debug, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub enum ControllerEvent {
     #[cfg(feature = "button_event")]
@@ -87,9 +162,186 @@ pub enum ControllerEvent {
     LocationEvent(LocationEvent),
 }
 
+impl ControllerEvent {
+    /// The type of the data package from which the event has been parsed.
+    pub fn data_package_type(&self) -> ControllerDataPackageType {
+        match self {
+            #[cfg(feature = "button_event")]
+            ControllerEvent::ButtonEvent(_) => ControllerDataPackageType::ButtonCommand,
+            #[cfg(feature = "color_event")]
+            ControllerEvent::ColorEvent(_) => ControllerDataPackageType::Color,
+            #[cfg(feature = "quaternion_event")]
+            ControllerEvent::QuaternionEvent(_) => ControllerDataPackageType::Quaternion,
+            #[cfg(feature = "accelerometer_event")]
+            ControllerEvent::AccelerometerEvent(_) => ControllerDataPackageType::Accelerometer,
+            #[cfg(feature = "gyro_event")]
+            ControllerEvent::GyroEvent(_) => ControllerDataPackageType::Gyro,
+            #[cfg(feature = "magnetometer_event")]
+            ControllerEvent::MagnetometerEvent(_) => ControllerDataPackageType::Magnetometer,
+            #[cfg(feature = "location_event")]
+            ControllerEvent::LocationEvent(_) => ControllerDataPackageType::Location,
+        }
+    }
+
+    /// Encode the event as a message of the protocol (incl. the CRC), this is the inverse of the [`Parser`].
+    pub fn to_frame(&self) -> Frame {
+        let mut frame = Frame::new(self.data_package_type());
+        match self {
+            #[cfg(feature = "button_event")]
+            ControllerEvent::ButtonEvent(e) => frame.push(&[e.button().id(), e.state().id()]),
+            #[cfg(feature = "color_event")]
+            ControllerEvent::ColorEvent(e) => frame.push(&[e.red(), e.green(), e.blue()]),
+            #[cfg(feature = "quaternion_event")]
+            ControllerEvent::QuaternionEvent(e) => frame.push_f32s(&[e.x(), e.y(), e.z(), e.w()]),
+            #[cfg(feature = "accelerometer_event")]
+            ControllerEvent::AccelerometerEvent(e) => frame.push_f32s(&[e.x(), e.y(), e.z()]),
+            #[cfg(feature = "gyro_event")]
+            ControllerEvent::GyroEvent(e) => frame.push_f32s(&[e.x(), e.y(), e.z()]),
+            #[cfg(feature = "magnetometer_event")]
+            ControllerEvent::MagnetometerEvent(e) => frame.push_f32s(&[e.x(), e.y(), e.z()]),
+            #[cfg(feature = "location_event")]
+            ControllerEvent::LocationEvent(e) => {
+                frame.push_f32s(&[e.latitude(), e.longitude(), e.altitude()])
+            }
+        }
+        frame.finish()
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for ControllerEvent {
+    /// Writes the text form of the event, see [`text`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            #[cfg(feature = "button_event")]
+            ControllerEvent::ButtonEvent(e) => e.fmt(f),
+            #[cfg(feature = "color_event")]
+            ControllerEvent::ColorEvent(e) => e.fmt(f),
+            #[cfg(feature = "quaternion_event")]
+            ControllerEvent::QuaternionEvent(e) => e.fmt(f),
+            #[cfg(feature = "accelerometer_event")]
+            ControllerEvent::AccelerometerEvent(e) => e.fmt(f),
+            #[cfg(feature = "gyro_event")]
+            ControllerEvent::GyroEvent(e) => e.fmt(f),
+            #[cfg(feature = "magnetometer_event")]
+            ControllerEvent::MagnetometerEvent(e) => e.fmt(f),
+            #[cfg(feature = "location_event")]
+            ControllerEvent::LocationEvent(e) => e.fmt(f),
+        }
+    }
+}
+
+impl FromStr for ControllerEvent {
+    type Err = TextParseError;
+
+    /// Parse the text form of any event, see [`text`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let command = Tokens::new(s).event()?;
+        match command {
+            ControllerDataPackageType::ButtonCommand => {
+                #[cfg(feature = "button_event")]
+                return s.parse().map(ControllerEvent::ButtonEvent);
+                #[cfg(not(feature = "button_event"))]
+                return Err(TextParseError::DisabledControllerDataPackageType(command));
+            }
+            ControllerDataPackageType::Color => {
+                #[cfg(feature = "color_event")]
+                return s.parse().map(ControllerEvent::ColorEvent);
+                #[cfg(not(feature = "color_event"))]
+                return Err(TextParseError::DisabledControllerDataPackageType(command));
+            }
+            ControllerDataPackageType::Quaternion => {
+                #[cfg(feature = "quaternion_event")]
+                return s.parse().map(ControllerEvent::QuaternionEvent);
+                #[cfg(not(feature = "quaternion_event"))]
+                return Err(TextParseError::DisabledControllerDataPackageType(command));
+            }
+            ControllerDataPackageType::Accelerometer => {
+                #[cfg(feature = "accelerometer_event")]
+                return s.parse().map(ControllerEvent::AccelerometerEvent);
+                #[cfg(not(feature = "accelerometer_event"))]
+                return Err(TextParseError::DisabledControllerDataPackageType(command));
+            }
+            ControllerDataPackageType::Gyro => {
+                #[cfg(feature = "gyro_event")]
+                return s.parse().map(ControllerEvent::GyroEvent);
+                #[cfg(not(feature = "gyro_event"))]
+                return Err(TextParseError::DisabledControllerDataPackageType(command));
+            }
+            ControllerDataPackageType::Magnetometer => {
+                #[cfg(feature = "magnetometer_event")]
+                return s.parse().map(ControllerEvent::MagnetometerEvent);
+                #[cfg(not(feature = "magnetometer_event"))]
+                return Err(TextParseError::DisabledControllerDataPackageType(command));
+            }
+            ControllerDataPackageType::Location => {
+                #[cfg(feature = "location_event")]
+                return s.parse().map(ControllerEvent::LocationEvent);
+                #[cfg(not(feature = "location_event"))]
+                return Err(TextParseError::DisabledControllerDataPackageType(command));
+            }
+        };
+    }
+}
+
+/// Maximum length of a message created by [`ControllerEvent::to_frame`].
+const MAX_FRAME_LENGTH: usize = 4 * BYTES_PER_FLOAT + 3; // ! + command + data + CRC
+
+/// A complete message of the protocol (incl. the CRC), see [`ControllerEvent::to_frame`].
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    buffer: [u8; MAX_FRAME_LENGTH],
+    len: usize,
+}
+
+impl Frame {
+    /// Start a message of the given type, the data is added with [`Frame::push`].
+    fn new(command: ControllerDataPackageType) -> Self {
+        let mut frame = Self {
+            buffer: [0; MAX_FRAME_LENGTH],
+            len: 0,
+        };
+        frame.push(&[b'!', command.id()]);
+        frame
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buffer[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    #[allow(unused)] // can be unused if no event which needs this has been selected as a feature.
+    fn push_f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.push(&value.to_le_bytes());
+        }
+    }
+
+    /// Append the CRC.
+    fn finish(mut self) -> Self {
+        let crc = calculate_crc(&self.buffer[..self.len]);
+        self.push(&[crc]);
+        self
+    }
+
+    /// The bytes of the message.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for Frame {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
 /// Represents the different kinds of errors which can happen when the protocol is being parsed.
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum ProtocolParseError {
     /// The message contained an event which is not known to the current implementation.
     /// This can mean that:
@@ -107,8 +359,14 @@ pub enum ProtocolParseError {
     InvalidCrc(u8, u16),
     /// There was a problem parsing a float from a message. The parameter gives the length of the received input.
     InvalidFloatSize(usize),
+    /// The message could not be authenticated, see [`authentication`].
+    #[cfg(feature = "authentication")]
+    AuthenticationError(AuthenticationError),
+    /// The values of the event are not plausible, see [`validation`].
+    ValidationError(ValidationError),
 }
 
+#[cfg(feature = "fmt")]
 impl Display for ProtocolParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         use ProtocolParseError::*;
@@ -117,6 +375,7 @@ impl Display for ProtocolParseError {
             DisabledControllerDataPackageType(event) => {
                 write!(f, "Disabled event type: {:?}", event)
             }
+            #[cfg(feature = "button_event")]
             ButtonParseError(_) => write!(f, "Error while parsing button event"),
             InvalidLength(expected, actual) => write!(
                 f,
@@ -133,15 +392,22 @@ impl Display for ProtocolParseError {
                 "Failed to parse float from a message with size {}",
                 length
             ),
+            #[cfg(feature = "authentication")]
+            AuthenticationError(_) => write!(f, "Failed to authenticate message"),
+            ValidationError(_) => write!(f, "Invalid values in message"),
         }
     }
 }
 
+#[cfg(feature = "fmt")]
 impl Error for ProtocolParseError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        use ProtocolParseError::*;
         match self {
-            ButtonParseError(e) => Some(e),
+            #[cfg(feature = "button_event")]
+            ProtocolParseError::ButtonParseError(e) => Some(e),
+            #[cfg(feature = "authentication")]
+            ProtocolParseError::AuthenticationError(e) => Some(e),
+            ProtocolParseError::ValidationError(e) => Some(e),
             _ => None,
         }
     }
@@ -150,6 +416,9 @@ impl Error for ProtocolParseError {
 /// Lists all data packages which can be sent by the controller. Internal state used during parsing. Use [`ControllerEvent`] to return the actual event.
 #[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub enum ControllerDataPackageType {
     ButtonCommand,
@@ -161,12 +430,28 @@ pub enum ControllerDataPackageType {
     Location,
 }
 
+/// Number of variants of [`ControllerDataPackageType`], used for arrays which keep a value per type and are indexed with `as usize`.
+const DATA_PACKAGE_TYPE_COUNT: usize = 7;
+
 const BYTES_PER_FLOAT: usize = 4;
 
 /// Maximum length of a controller message which must be tolerated by any caller.
 pub const MAX_CONTROLLER_MESSAGE_LENGTH: usize = 32; // give slightly more than necessary to avoid cutting off an unexpected message
 
 impl ControllerDataPackageType {
+    /// The ID of the type in the protocol, this is the inverse of `TryFrom<u8>`.
+    pub fn id(&self) -> u8 {
+        match self {
+            ControllerDataPackageType::ButtonCommand => b'B',
+            ControllerDataPackageType::Color => b'C',
+            ControllerDataPackageType::Quaternion => b'Q',
+            ControllerDataPackageType::Accelerometer => b'A',
+            ControllerDataPackageType::Gyro => b'G',
+            ControllerDataPackageType::Magnetometer => b'M',
+            ControllerDataPackageType::Location => b'L',
+        }
+    }
+
     /// Returns the length of the data section of the command.
     fn data_len(&self) -> usize {
         match self {
@@ -247,6 +532,7 @@ impl TryFrom<u8> for ControllerDataPackageType {
 ///         e,
 ///         &ProtocolParseError::ButtonParseError(ButtonParseError::UnknownButtonState(b'3'))
 ///     );
+///     # #[cfg(feature = "fmt")]
 ///     # {
 ///         // test the `core::error::Error` implementation
 ///         # extern crate alloc;
@@ -266,12 +552,54 @@ impl TryFrom<u8> for ControllerDataPackageType {
 pub struct Parser<'a> {
     input: &'a [u8],
     curr_pos: usize,
+    options: ParseOptions,
 }
 
 impl<'a> Parser<'a> {
     /// Create a new parser. The input is parsed step by step on each invocation of `next`.
     pub fn new(input: &'a [u8]) -> Self {
-        Self { input, curr_pos: 0 }
+        Self {
+            input,
+            curr_pos: 0,
+            options: ParseOptions::default(),
+        }
+    }
+
+    /// Define how button events are parsed. By default [`ButtonParseMode::Strict`] is used.
+    #[cfg(feature = "button_event")]
+    pub fn with_button_parse_mode(mut self, mode: ButtonParseMode) -> Self {
+        self.options.button_parse_mode = mode;
+        self
+    }
+
+    /// Reject events with implausible values, see [`validation`]. By default no validation is done.
+    pub fn with_validation(mut self, validation: Validation) -> Self {
+        self.options.validation = Some(validation);
+        self
+    }
+}
+
+/// Options which influence how the commands are parsed.
+#[derive(Debug, Copy, Clone)]
+struct ParseOptions {
+    #[cfg(feature = "button_event")]
+    button_parse_mode: ButtonParseMode,
+    validation: Option<Validation>,
+}
+
+impl ParseOptions {
+    const fn new() -> Self {
+        Self {
+            #[cfg(feature = "button_event")]
+            button_parse_mode: ButtonParseMode::Strict,
+            validation: None,
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -295,8 +623,18 @@ impl Iterator for Parser<'_> {
                     }
                 }
                 ParserState::ParseCommand => {
-                    self.curr_pos = pos;
-                    return Some(extract_and_parse_command(&self.input[(pos - 1)..]));
+                    let result = extract_and_parse_command(&self.input[(pos - 1)..], &self.options);
+                    // continue after a valid command (its data might contain a `!`), otherwise search the next start
+                    self.curr_pos = match &result {
+                        Ok(event) => pos + event.data_package_type().data_len() + 2,
+                        Err(ProtocolParseError::ValidationError(_)) => {
+                            // the command has been parsed completely, so its type is known
+                            pos + ControllerDataPackageType::try_from(byte)
+                                .map_or(0, |command| command.data_len() + 2)
+                        }
+                        Err(_) => pos,
+                    };
+                    return Some(result);
                 }
             };
         }
@@ -306,16 +644,20 @@ impl Iterator for Parser<'_> {
 }
 
 /// Extract a command and then try to parse it.
-fn extract_and_parse_command(input: &[u8]) -> Result<ControllerEvent, ProtocolParseError> {
+fn extract_and_parse_command(
+    input: &[u8],
+    options: &ParseOptions,
+) -> Result<ControllerEvent, ProtocolParseError> {
     let command = ControllerDataPackageType::try_from(input[1])?;
     let command_end = min(command.data_len() + 2, input.len() - 1);
-    parse_command(command, &input[..=command_end])
+    parse_command(command, &input[..=command_end], options)
 }
 
 /// Parse a command (which has previously been extracted by [`parse`]).
 fn parse_command(
     command: ControllerDataPackageType,
     command_input: &[u8],
+    options: &ParseOptions,
 ) -> Result<ControllerEvent, ProtocolParseError> {
     #[cfg(feature = "defmt")]
     defmt::debug!(
@@ -339,11 +681,28 @@ fn parse_command(
     check_crc(&command_input[..=data_end], crc)?;
 
     // parse the actual command based on its type
-    let data = &command_input[data_start..=data_end];
+    let event = parse_data(command, &command_input[data_start..=data_end], options)?;
+
+    match &options.validation {
+        Some(validation) => validation
+            .validate(&event)
+            .map(|()| event)
+            .map_err(ProtocolParseError::ValidationError),
+        None => Ok(event),
+    }
+}
+
+/// Parse the data of a command (which has previously been validated by [`parse_command`]).
+fn parse_data(
+    command: ControllerDataPackageType,
+    data: &[u8],
+    #[allow(unused)] options: &ParseOptions, // only used for some of the events
+) -> Result<ControllerEvent, ProtocolParseError> {
     match command {
         ControllerDataPackageType::ButtonCommand => {
             #[cfg(feature = "button_event")]
-            return ButtonEvent::try_from(data).map(ControllerEvent::ButtonEvent);
+            return ButtonEvent::parse(data, options.button_parse_mode)
+                .map(ControllerEvent::ButtonEvent);
             #[cfg(not(feature = "button_event"))]
             return Err(ProtocolParseError::DisabledControllerDataPackageType(
                 command,
@@ -405,12 +764,7 @@ fn check_crc(data: &[u8], crc: &u8) -> Result<(), ProtocolParseError> {
     #[cfg(feature = "defmt")]
     defmt::trace!("calculating CRC for {:a}, expecting {}", data, crc);
 
-    let mut sum: u16 = 0;
-    for byte in data {
-        sum += *byte as u16;
-    }
-
-    let calculated_crc = !sum & 0xff;
+    let calculated_crc = calculate_crc(data) as u16;
 
     if *crc as u16 == calculated_crc {
         Ok(())
@@ -419,6 +773,12 @@ fn check_crc(data: &[u8], crc: &u8) -> Result<(), ProtocolParseError> {
     }
 }
 
+/// Calculate the CRC of a command: the inverted sum of all bytes.
+fn calculate_crc(data: &[u8]) -> u8 {
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    !sum
+}
+
 /// Small wrapper to convert the 4-byte value to an `f32` and handle the error.
 #[allow(unused)] // can be unused if no event which needs this has been selected as a feature.
 fn try_f32_from_le_bytes(input: &[u8]) -> Result<f32, ProtocolParseError> {
@@ -427,6 +787,15 @@ fn try_f32_from_le_bytes(input: &[u8]) -> Result<f32, ProtocolParseError> {
     )?))
 }
 
+/// Absolute value of a float, `f32::abs` is not available in `core` for the MSRV.
+fn abs_f32(value: f32) -> f32 {
+    if value < 0.0 {
+        -value
+    } else {
+        value
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{check_crc, try_f32_from_le_bytes, ProtocolParseError};
@@ -437,7 +806,7 @@ mod tests {
         let data = &input[..input.len() - 1];
         let crc = input.last().unwrap();
 
-        assert!(check_crc(data, &crc).is_ok());
+        assert!(check_crc(data, crc).is_ok());
     }
 
     #[test]
@@ -448,7 +817,7 @@ mod tests {
         let crc = input.last().unwrap();
 
         assert_eq!(
-            check_crc(data, &crc),
+            check_crc(data, crc),
             Err(ProtocolParseError::InvalidCrc(*crc, correct_crc as u16))
         );
     }
@@ -470,4 +839,181 @@ mod tests {
             Err(ProtocolParseError::InvalidFloatSize(3))
         );
     }
+
+    #[test]
+    #[cfg(feature = "button_event")]
+    fn test_parser_lenient_button_parse_mode() {
+        use crate::button_event::{Button, ButtonParseMode};
+        use crate::{ControllerEvent, Parser};
+
+        let input = b"!B912";
+        assert!(matches!(
+            Parser::new(input).next(),
+            Some(Err(ProtocolParseError::ButtonParseError(_)))
+        ));
+        match Parser::new(input)
+            .with_button_parse_mode(ButtonParseMode::Lenient)
+            .next()
+        {
+            Some(Ok(ControllerEvent::ButtonEvent(event))) => {
+                assert_eq!(event.button(), &Button::Other(b'9'))
+            }
+            _ => panic!("expected a button event"),
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "button_event", feature = "color_event"))]
+    fn test_text_form_round_trip() {
+        use crate::Parser;
+
+        let input = b"!B11:!C\xff-96!B219";
+        for (event, text) in
+            Parser::new(input).zip(["button 1 pressed", "color #ff2d39", "button 2 pressed"])
+        {
+            let event = event.unwrap();
+            assert_eq!(text.parse(), Ok(event));
+            #[cfg(feature = "fmt")]
+            {
+                extern crate alloc;
+                use alloc::string::ToString;
+                assert_eq!(event.to_string(), text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_form_unknown_event() {
+        use crate::text::TextParseError;
+        use crate::ControllerEvent;
+
+        assert_eq!(
+            "light on".parse::<ControllerEvent>(),
+            Err(TextParseError::UnknownEvent)
+        );
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        use crate::Parser;
+
+        let input: &[&[u8]] = &[
+            b"!B11:",
+            b"!B10;",
+            b"!C\xff-96",
+            b"!Q9\x1e\x0c\xc03\xf7P\xbf\xefv\x96>\x00\x00\x00\x00\xf8",
+            b"!A\x00\x00\x00\x00\x00\x00\x80?\x00\x00\x00\xc0\x1e",
+            b"!GH.\xd7;\x0c\xb2\xe8<z\xe62\xbd\xde",
+            b"!M\xcd\xcc\x8bA\x00@\x03\xc2\x9a\x19\xcb\xc1\xe8",
+            b"!L\x9a\x99\x99?33\x13@\x9a\x99Y@\x02",
+        ];
+        for frame in input {
+            match Parser::new(frame).next() {
+                Some(Ok(event)) => assert_eq!(event.to_frame().as_bytes(), *frame),
+                Some(Err(ProtocolParseError::DisabledControllerDataPackageType(_))) => {}
+                result => panic!("unexpected result {:?} for {:?}", result, frame),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "button_event", feature = "color_event"))]
+    fn test_serde_representation() {
+        use crate::button_event::{Button, ButtonEvent, ButtonState};
+        use crate::color_event::ColorEvent;
+        use crate::ControllerEvent;
+
+        let cases = [
+            (
+                ControllerEvent::ButtonEvent(ButtonEvent::new(Button::Up, ButtonState::Pressed)),
+                r#"{"ButtonEvent":{"button":"Up","state":"Pressed"}}"#,
+            ),
+            (
+                ControllerEvent::ButtonEvent(ButtonEvent::new(
+                    Button::Other(b'9'),
+                    ButtonState::Released,
+                )),
+                r#"{"ButtonEvent":{"button":{"Other":57},"state":"Released"}}"#,
+            ),
+            (
+                ControllerEvent::ColorEvent(ColorEvent::new(255, 45, 57)),
+                r#"{"ColorEvent":{"red":255,"green":45,"blue":57}}"#,
+            ),
+        ];
+        for (event, json) in cases {
+            assert_eq!(serde_json::to_string(&event).unwrap(), json);
+            assert_eq!(
+                serde_json::from_str::<ControllerEvent>(json).unwrap(),
+                event
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "button_event"))]
+    fn test_serde_round_trip() {
+        extern crate alloc;
+        use crate::{ControllerEvent, Parser};
+        use alloc::vec::Vec;
+
+        let input = b"!B11:!C\xff-96!B138!Q9\x1e\x0c\xc03\xf7P\xbf\xefv\x96>\x00\x00\x00\x00\xf8!X";
+        let events: Vec<Result<ControllerEvent, ProtocolParseError>> = Parser::new(input).collect();
+        assert_eq!(events.len(), 5);
+
+        let json = serde_json::to_string(&events).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<Result<ControllerEvent, ProtocolParseError>>>(&json)
+                .unwrap(),
+            events
+        );
+
+        let bytes = postcard::to_allocvec(&events).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<Vec<Result<ControllerEvent, ProtocolParseError>>>(&bytes)
+                .unwrap(),
+            events
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "schemars")]
+    fn test_json_schema() {
+        use crate::ControllerEvent;
+
+        let schema = serde_json::to_string(&schemars::schema_for!(ControllerEvent)).unwrap();
+        #[cfg(feature = "button_event")]
+        assert!(schema.contains(r#""ButtonEvent""#));
+        #[cfg(feature = "color_event")]
+        assert!(schema.contains(r#""ColorEvent""#));
+        #[cfg(feature = "quaternion_event")]
+        assert!(schema.contains(r#""QuaternionEvent""#));
+        #[cfg(feature = "accelerometer_event")]
+        assert!(schema.contains(r#""AccelerometerEvent""#));
+        #[cfg(feature = "gyro_event")]
+        assert!(schema.contains(r#""GyroEvent""#));
+        #[cfg(feature = "magnetometer_event")]
+        assert!(schema.contains(r#""MagnetometerEvent""#));
+        #[cfg(feature = "location_event")]
+        assert!(schema.contains(r#""LocationEvent""#));
+    }
+
+    #[test]
+    #[cfg(all(feature = "button_event", feature = "color_event"))]
+    fn test_parser_skips_data_of_valid_command() {
+        use crate::Parser;
+
+        // the green value of the color is `!`
+        let input = b"!C\x01!\x03v!B11:";
+        let mut parser = Parser::new(input);
+
+        assert!(matches!(
+            parser.next(),
+            Some(Ok(crate::ControllerEvent::ColorEvent(_)))
+        ));
+        assert!(matches!(
+            parser.next(),
+            Some(Ok(crate::ControllerEvent::ButtonEvent(_)))
+        ));
+        assert_eq!(parser.next(), None);
+    }
 }
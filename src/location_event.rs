@@ -1,11 +1,17 @@
 //! Implements the [`LocationEvent`] and its parsing from the protocol.
 
-use super::{try_f32_from_le_bytes, ProtocolParseError};
+use super::text::{TextParseError, Tokens};
+use super::{try_f32_from_le_bytes, ControllerDataPackageType, ProtocolParseError};
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 
 /// Represents a location event from the protocol.
 #[derive(PartialEq, Debug, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub struct LocationEvent {
     latitude: f32,
@@ -33,8 +39,60 @@ impl TryFrom<&[u8]> for LocationEvent {
     }
 }
 
+impl FromStr for LocationEvent {
+    type Err = TextParseError;
+
+    /// Parse the text form of the event (e.g. `location lat=47.37 lon=8.54 alt=408.0`), see [`text`](super::text).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Tokens::new(s);
+        tokens.expect_event(ControllerDataPackageType::Location)?;
+        let event = LocationEvent {
+            latitude: tokens.named_f32("lat")?,
+            longitude: tokens.named_f32("lon")?,
+            altitude: tokens.named_f32("alt")?,
+        };
+        tokens.end()?;
+        Ok(event)
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for LocationEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "location lat={:?} lon={:?} alt={:?}",
+            self.latitude, self.longitude, self.altitude
+        )
+    }
+}
+
 #[allow(missing_docs)] // the names are already obvious enough
 impl LocationEvent {
+    /// Create the event, e.g. `LocationEvent::new(47.37, 8.54, 408.0)`.
+    pub const fn new(latitude: f32, longitude: f32, altitude: f32) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude,
+        }
+    }
+
+    pub const fn with_latitude(mut self, latitude: f32) -> Self {
+        self.latitude = latitude;
+        self
+    }
+
+    pub const fn with_longitude(mut self, longitude: f32) -> Self {
+        self.longitude = longitude;
+        self
+    }
+
+    pub const fn with_altitude(mut self, altitude: f32) -> Self {
+        self.altitude = altitude;
+        self
+    }
+
     pub fn latitude(&self) -> f32 {
         self.latitude
     }
@@ -73,4 +131,42 @@ mod tests {
 
         assert_eq!(LocationEvent::try_from(input.as_slice()), Ok(expected));
     }
+
+    #[test]
+    fn test_text_form() {
+        let text = "location lat=1.2 lon=2.3 alt=3.4";
+        let event = LocationEvent {
+            latitude: 1.2,
+            longitude: 2.3,
+            altitude: 3.4,
+        };
+
+        assert_eq!(text.parse(), Ok(event));
+        #[cfg(feature = "fmt")]
+        {
+            extern crate alloc;
+            use alloc::string::ToString;
+            assert_eq!(event.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let event = LocationEvent::new(1.2, 2.3, 3.4);
+        assert_eq!(
+            event,
+            LocationEvent {
+                latitude: 1.2,
+                longitude: 2.3,
+                altitude: 3.4,
+            }
+        );
+        assert_eq!(
+            event
+                .with_latitude(0.0)
+                .with_longitude(0.0)
+                .with_altitude(0.0),
+            LocationEvent::new(0.0, 0.0, 0.0)
+        );
+    }
 }
@@ -1,11 +1,17 @@
 //! Implements the [`MagnetometerEvent`] and its parsing from the protocol.
 
-use super::{try_f32_from_le_bytes, ProtocolParseError};
+use super::text::{TextParseError, Tokens};
+use super::{try_f32_from_le_bytes, ControllerDataPackageType, ProtocolParseError};
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 
 /// Represents a magnetometer event from the protocol.
 #[derive(PartialEq, Debug, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub struct MagnetometerEvent {
     x: f32,
@@ -33,8 +39,52 @@ impl TryFrom<&[u8]> for MagnetometerEvent {
     }
 }
 
+impl FromStr for MagnetometerEvent {
+    type Err = TextParseError;
+
+    /// Parse the text form of the event (e.g. `mag x=0.1 y=-0.2 z=0.3`), see [`text`](super::text).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Tokens::new(s);
+        tokens.expect_event(ControllerDataPackageType::Magnetometer)?;
+        let event = MagnetometerEvent {
+            x: tokens.named_f32("x")?,
+            y: tokens.named_f32("y")?,
+            z: tokens.named_f32("z")?,
+        };
+        tokens.end()?;
+        Ok(event)
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for MagnetometerEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "mag x={:?} y={:?} z={:?}", self.x, self.y, self.z)
+    }
+}
+
 #[allow(missing_docs)] // the names are already obvious enough
 impl MagnetometerEvent {
+    /// Create the event, e.g. `MagnetometerEvent::new(17.5, -32.8, -25.4)`.
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub const fn with_x(mut self, x: f32) -> Self {
+        self.x = x;
+        self
+    }
+
+    pub const fn with_y(mut self, y: f32) -> Self {
+        self.y = y;
+        self
+    }
+
+    pub const fn with_z(mut self, z: f32) -> Self {
+        self.z = z;
+        self
+    }
+
     pub fn x(&self) -> f32 {
         self.x
     }
@@ -63,4 +113,36 @@ mod tests {
 
         assert_eq!(MagnetometerEvent::try_from(input), Ok(expected));
     }
+
+    #[test]
+    fn test_text_form() {
+        let text = "mag x=17.475 y=-32.8125 z=-25.3875";
+        let event = MagnetometerEvent {
+            x: 17.475,
+            y: -32.8125,
+            z: -25.3875,
+        };
+
+        assert_eq!(text.parse(), Ok(event));
+        #[cfg(feature = "fmt")]
+        {
+            extern crate alloc;
+            use alloc::string::ToString;
+            assert_eq!(event.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_invalid_text_form() {
+        use crate::text::TextParseError;
+
+        assert_eq!(
+            "mag x=1 y=2".parse::<MagnetometerEvent>(),
+            Err(TextParseError::InvalidValue(3))
+        );
+        assert_eq!(
+            "mag x=1 y=a z=3".parse::<MagnetometerEvent>(),
+            Err(TextParseError::InvalidValue(2))
+        );
+    }
 }
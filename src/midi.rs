@@ -0,0 +1,533 @@
+//! Implements the [`MidiMapper`] which turns the controller into a simple MIDI controller.
+
+use super::button_event::{Button, ButtonEvent, ButtonState};
+use super::button_states::ButtonSet;
+#[cfg(feature = "color_event")]
+use super::color_event::ColorEvent;
+#[cfg(feature = "quaternion_event")]
+use super::quaternion_event::QuaternionEvent;
+use super::time::{Duration, Instant};
+use super::ControllerEvent;
+
+/// A MIDI channel message, see [`MidiMessage::to_bytes`] for the raw message.
+///
+/// The channel (0 to 15) and the data bytes (0 to 127) are masked when encoding the message.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[allow(missing_docs)] // the names are already obvious enough
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+}
+
+impl MidiMessage {
+    /// The raw 3-byte message, e.g. `[0x90, 60, 100]` for a note on of middle C on the first channel.
+    pub const fn to_bytes(&self) -> [u8; 3] {
+        let (status, channel, data1, data2) = match *self {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => (0x90, channel, note, velocity),
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => (0x80, channel, note, velocity),
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => (0xb0, channel, controller, value),
+        };
+        [status | channel & 0x0f, data1 & 0x7f, data2 & 0x7f]
+    }
+}
+
+/// The note (and its channel) played by a button.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Note {
+    channel: u8,
+    note: u8,
+}
+
+/// The state of an axis which is mapped to a controller.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Axis {
+    controller: Option<u8>,
+    last_sent: Option<(Instant, u8)>,
+    pending: Option<u8>,
+}
+
+impl Axis {
+    const fn new(controller: u8) -> Self {
+        Self {
+            controller: Some(controller),
+            last_sent: None,
+            pending: None,
+        }
+    }
+}
+
+/// The number of axes: red, green & blue of the color and x, y, z & w of the quaternion.
+const AXIS_COUNT: usize = 7;
+/// The index of the first quaternion axis.
+#[cfg(feature = "quaternion_event")]
+const QUATERNION_AXES: usize = 3;
+
+/// The MIDI messages produced by a single event, see [`MidiMapper::process`].
+#[derive(Debug, Clone)]
+pub struct MidiMessages {
+    messages: [Option<MidiMessage>; 4],
+    index: usize,
+}
+
+impl MidiMessages {
+    const fn new() -> Self {
+        Self {
+            messages: [None; 4],
+            index: 0,
+        }
+    }
+
+    fn push(&mut self, message: MidiMessage) {
+        if let Some(slot) = self.messages.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(message);
+        }
+    }
+}
+
+impl Iterator for MidiMessages {
+    type Item = MidiMessage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = self.messages.get_mut(self.index)?.take();
+        self.index += 1;
+        message
+    }
+}
+
+/// Maps the buttons to notes and the color picker & the orientation of the phone to control changes (CC).
+///
+/// * a press of a button results in a note on, the release in a note off. By default, the buttons 1 to 4 and then
+///   up, down, left & right play the C major scale from middle C (60) on the first channel. Repeated presses and
+///   releases are ignored.
+/// * red, green & blue of a [`ColorEvent`] are mapped to the controllers 20 to 22 and x, y & z of a
+///   [`QuaternionEvent`] to the controllers 23 to 25 (w is not mapped). The colors are scaled from 0..255, the
+///   quaternion from -1..1 to the MIDI range 0..127.
+/// * the control changes are rate limited: per controller at most one message is sent per interval and only if the
+///   value has changed. The latest value received in between is not lost, it is returned by [`MidiMapper::poll`] once
+///   the interval has elapsed.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::button_event::Button;
+/// # use adafruit_bluefruit_protocol::midi::MidiMapper;
+/// # use adafruit_bluefruit_protocol::time::Instant;
+/// # use adafruit_bluefruit_protocol::Parser;
+/// let mut midi = MidiMapper::new().with_note(Button::Up, 9, 38).with_velocity(127);
+/// let now = Instant::from_millis(0);
+/// // up is pressed and released
+/// let mut bytes = Parser::new(b"!B516!B507")
+///     .flat_map(|event| midi.process(&event.unwrap(), now))
+///     .map(|message| message.to_bytes());
+/// assert_eq!(bytes.next(), Some([0x99, 38, 127]));
+/// assert_eq!(bytes.next(), Some([0x89, 38, 64]));
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MidiMapper {
+    notes: [Option<Note>; Button::KNOWN.len()],
+    velocity: u8,
+    held: ButtonSet,
+    cc_channel: u8,
+    interval: Duration,
+    axes: [Axis; AXIS_COUNT],
+}
+
+impl MidiMapper {
+    /// Create the mapper with the default notes & controllers, a velocity of 100 and an interval of 20ms.
+    pub const fn new() -> Self {
+        const fn note(note: u8) -> Option<Note> {
+            Some(Note { channel: 0, note })
+        }
+        Self {
+            notes: [
+                note(60),
+                note(62),
+                note(64),
+                note(65),
+                note(67),
+                note(69),
+                note(71),
+                note(72),
+            ],
+            velocity: 100,
+            held: ButtonSet::new(),
+            cc_channel: 0,
+            interval: Duration::from_millis(20),
+            axes: [
+                Axis::new(20),
+                Axis::new(21),
+                Axis::new(22),
+                Axis::new(23),
+                Axis::new(24),
+                Axis::new(25),
+                Axis {
+                    controller: None,
+                    last_sent: None,
+                    pending: None,
+                },
+            ],
+        }
+    }
+
+    /// Play the note on the channel when the button is pressed. [`Button::Other`] can not be mapped, it is ignored.
+    pub const fn with_note(mut self, button: Button, channel: u8, note: u8) -> Self {
+        if let Some(index) = button.index() {
+            self.notes[index] = Some(Note { channel, note });
+        }
+        self
+    }
+
+    /// Remove the note of the button, it is ignored afterwards.
+    pub const fn without_note(mut self, button: Button) -> Self {
+        if let Some(index) = button.index() {
+            self.notes[index] = None;
+        }
+        self
+    }
+
+    /// Set the velocity of the note on messages. Note off messages always have a velocity of 64.
+    pub const fn with_velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    /// Set the channel of the control changes.
+    pub const fn with_cc_channel(mut self, channel: u8) -> Self {
+        self.cc_channel = channel;
+        self
+    }
+
+    /// Set the minimum time between two control changes of the same controller.
+    pub const fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the controllers of red, green & blue, `None` disables the control changes of the color.
+    #[cfg(feature = "color_event")]
+    pub const fn with_color_controllers(mut self, controllers: Option<[u8; 3]>) -> Self {
+        let mut index = 0;
+        while index < 3 {
+            self.axes[index].controller = match controllers {
+                Some(controllers) => Some(controllers[index]),
+                None => None,
+            };
+            index += 1;
+        }
+        self
+    }
+
+    /// Set the controllers of x, y, z & w of the quaternion, `None` disables the control change of the component.
+    #[cfg(feature = "quaternion_event")]
+    pub const fn with_quaternion_controllers(mut self, controllers: [Option<u8>; 4]) -> Self {
+        let mut index = 0;
+        while index < 4 {
+            self.axes[QUATERNION_AXES + index].controller = controllers[index];
+            index += 1;
+        }
+        self
+    }
+
+    /// The messages for the event received at `now`, all other events are ignored.
+    pub fn process(&mut self, event: &ControllerEvent, now: Instant) -> MidiMessages {
+        match event {
+            ControllerEvent::ButtonEvent(event) => self.process_button(event).into_iter().collect(),
+            #[cfg(feature = "color_event")]
+            ControllerEvent::ColorEvent(event) => self.process_color(event, now),
+            #[cfg(feature = "quaternion_event")]
+            ControllerEvent::QuaternionEvent(event) => self.process_quaternion(event, now),
+            #[allow(unreachable_patterns)] // depends on the selected events
+            _ => {
+                let _ = now;
+                MidiMessages::new()
+            }
+        }
+    }
+
+    /// The note on or off for the button event, `None` if the button is not mapped or the event is a repetition.
+    pub fn process_button(&mut self, event: &ButtonEvent) -> Option<MidiMessage> {
+        let button = *event.button();
+        let Note { channel, note } = self.notes[button.index()?]?;
+        match event.state() {
+            ButtonState::Pressed if !self.held.contains(button) => {
+                self.held.insert(button);
+                Some(MidiMessage::NoteOn {
+                    channel,
+                    note,
+                    velocity: self.velocity,
+                })
+            }
+            ButtonState::Released if self.held.contains(button) => {
+                self.held.remove(button);
+                Some(note_off(channel, note))
+            }
+            _ => None,
+        }
+    }
+
+    /// The control changes for the color received at `now`.
+    #[cfg(feature = "color_event")]
+    pub fn process_color(&mut self, event: &ColorEvent, now: Instant) -> MidiMessages {
+        let mut messages = MidiMessages::new();
+        for (index, value) in [event.red(), event.green(), event.blue()]
+            .into_iter()
+            .enumerate()
+        {
+            if let Some(message) = self.update_axis(index, value >> 1, now) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    /// The control changes for the quaternion received at `now`.
+    #[cfg(feature = "quaternion_event")]
+    pub fn process_quaternion(&mut self, event: &QuaternionEvent, now: Instant) -> MidiMessages {
+        let mut messages = MidiMessages::new();
+        for (index, value) in [event.x(), event.y(), event.z(), event.w()]
+            .into_iter()
+            .enumerate()
+        {
+            // the cast saturates, so values out of range are clamped
+            let value = ((value + 1.0) * 63.5 + 0.5) as u8;
+            if let Some(message) = self.update_axis(QUATERNION_AXES + index, value.min(127), now) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    /// Returns the next control change which has been held back by the rate limit and is due at `now`.
+    ///
+    /// Call this regularly (e.g. every few milliseconds) until it returns `None`.
+    pub fn poll(&mut self, now: Instant) -> Option<MidiMessage> {
+        let (channel, interval) = (self.cc_channel, self.interval);
+        self.axes.iter_mut().find_map(|axis| match *axis {
+            Axis {
+                controller: Some(controller),
+                last_sent: Some((at, _)),
+                pending: Some(value),
+            } if now.saturating_duration_since(at) >= interval => {
+                axis.last_sent = Some((now, value));
+                axis.pending = None;
+                Some(control_change(channel, controller, value))
+            }
+            _ => None,
+        })
+    }
+
+    /// The note offs for all held buttons, e.g. when the connection has been lost. Pending control changes are dropped.
+    pub fn release_all(&mut self) -> impl Iterator<Item = MidiMessage> {
+        let held = core::mem::take(&mut self.held);
+        for axis in self.axes.iter_mut() {
+            axis.pending = None;
+        }
+        let notes = self.notes;
+        held.into_iter().filter_map(move |button| {
+            let Note { channel, note } = notes[button.index()?]?;
+            Some(note_off(channel, note))
+        })
+    }
+
+    #[allow(unused)] // not used if neither `color_event` nor `quaternion_event` has been selected
+    fn update_axis(&mut self, index: usize, value: u8, now: Instant) -> Option<MidiMessage> {
+        let axis = &mut self.axes[index];
+        let controller = axis.controller?;
+        match axis.last_sent {
+            Some((_, last)) if last == value => {
+                axis.pending = None;
+                None
+            }
+            Some((at, _)) if now.saturating_duration_since(at) < self.interval => {
+                axis.pending = Some(value);
+                None
+            }
+            _ => {
+                axis.last_sent = Some((now, value));
+                axis.pending = None;
+                Some(control_change(self.cc_channel, controller, value))
+            }
+        }
+    }
+}
+
+impl Default for MidiMapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromIterator<MidiMessage> for MidiMessages {
+    fn from_iter<T: IntoIterator<Item = MidiMessage>>(iter: T) -> Self {
+        let mut messages = MidiMessages::new();
+        for message in iter {
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+fn note_off(channel: u8, note: u8) -> MidiMessage {
+    MidiMessage::NoteOff {
+        channel,
+        note,
+        velocity: 64,
+    }
+}
+
+fn control_change(channel: u8, controller: u8, value: u8) -> MidiMessage {
+    MidiMessage::ControlChange {
+        channel,
+        controller,
+        value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::midi::{MidiMapper, MidiMessage};
+    use crate::time::Instant;
+    use crate::ControllerEvent;
+
+    fn bytes(message: Option<MidiMessage>) -> Option<[u8; 3]> {
+        message.map(|message| message.to_bytes())
+    }
+
+    fn button(midi: &mut MidiMapper, button: Button, state: ButtonState) -> Option<[u8; 3]> {
+        let event = ControllerEvent::ButtonEvent(ButtonEvent::new(button, state));
+        let mut messages = midi.process(&event, Instant::from_millis(0));
+        let message = messages.next();
+        assert_eq!(messages.next(), None);
+        bytes(message)
+    }
+
+    #[cfg(feature = "color_event")]
+    fn color(midi: &mut MidiMapper, red: u8, green: u8, millis: u64, expected: &[[u8; 3]]) {
+        let color = crate::color_event::ColorEvent::new(red, green, 0);
+        let messages = midi.process_color(&color, Instant::from_millis(millis));
+        assert!(messages
+            .map(|message| message.to_bytes())
+            .eq(expected.iter().copied()));
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let message = MidiMessage::ControlChange {
+            channel: 0x1f,
+            controller: 7,
+            value: 0xff,
+        };
+        assert_eq!(message.to_bytes(), [0xbf, 7, 0x7f]);
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut midi = MidiMapper::new()
+            .with_note(Button::Button2, 3, 40)
+            .without_note(Button::Down);
+
+        assert_eq!(
+            button(&mut midi, Button::Button1, ButtonState::Pressed),
+            Some([0x90, 60, 100])
+        );
+        assert_eq!(
+            button(&mut midi, Button::Button1, ButtonState::Pressed),
+            None
+        );
+        assert_eq!(
+            button(&mut midi, Button::Button2, ButtonState::Pressed),
+            Some([0x93, 40, 100])
+        );
+        assert_eq!(button(&mut midi, Button::Down, ButtonState::Pressed), None);
+        assert_eq!(
+            button(&mut midi, Button::Button1, ButtonState::Released),
+            Some([0x80, 60, 64])
+        );
+        assert_eq!(
+            button(&mut midi, Button::Button1, ButtonState::Released),
+            None
+        );
+
+        let mut released = midi.release_all().map(|message| message.to_bytes());
+        assert_eq!(released.next(), Some([0x83, 40, 64]));
+        assert_eq!(released.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "color_event")]
+    fn test_color_rate_limit() {
+        use crate::time::Duration;
+
+        let mut midi = MidiMapper::new()
+            .with_cc_channel(1)
+            .with_interval(Duration::from_millis(100));
+        color(
+            &mut midi,
+            255,
+            10,
+            0,
+            &[[0xb1, 20, 127], [0xb1, 21, 5], [0xb1, 22, 0]],
+        );
+        // unchanged values are not sent, changed ones are held back until the interval has elapsed
+        color(&mut midi, 255, 10, 10, &[]);
+        color(&mut midi, 255, 20, 50, &[]);
+        color(&mut midi, 255, 30, 60, &[]);
+        assert_eq!(midi.poll(Instant::from_millis(99)), None);
+        assert_eq!(
+            bytes(midi.poll(Instant::from_millis(100))),
+            Some([0xb1, 21, 15])
+        );
+        assert_eq!(midi.poll(Instant::from_millis(300)), None);
+        color(&mut midi, 0, 30, 300, &[[0xb1, 20, 0]]);
+    }
+
+    #[test]
+    #[cfg(feature = "quaternion_event")]
+    fn test_quaternion() {
+        use crate::quaternion_event::QuaternionEvent;
+
+        let mut midi =
+            MidiMapper::new().with_quaternion_controllers([Some(1), None, Some(2), Some(3)]);
+        let messages = midi.process_quaternion(
+            &QuaternionEvent::new(-1.0, 0.5, 0.0, 2.0),
+            Instant::from_millis(0),
+        );
+
+        assert!(messages.map(|message| message.to_bytes()).eq([
+            [0xb0, 1, 0],
+            [0xb0, 2, 64],
+            [0xb0, 3, 127]
+        ]));
+    }
+}
@@ -0,0 +1,538 @@
+//! Implements the [`MultiParser`] which parses the byte streams of several connections at the same time.
+//!
+//! Contrary to the [`Parser`](super::Parser) it does not need complete messages: partially received messages are
+//! kept per connection until the rest of the message arrives.
+
+#[cfg(feature = "button_event")]
+use super::button_event::ButtonParseMode;
+use super::validation::Validation;
+use super::{
+    parse_command, ControllerDataPackageType, ControllerEvent, ParseOptions, ProtocolParseError,
+    MAX_CONTROLLER_MESSAGE_LENGTH,
+};
+#[cfg(feature = "fmt")]
+use core::error::Error;
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+
+/// Identifies a connection, e.g. using the connection handle of the BLE stack.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ConnectionId(pub u16);
+
+/// Errors which can be raised by the [`MultiParser`].
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum MultiParserError {
+    /// All slots are in use by other connections. Call [`MultiParser::disconnect`] for connections which are gone.
+    NoFreeSlot(ConnectionId),
+}
+
+#[cfg(feature = "fmt")]
+impl Display for MultiParserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        use MultiParserError::*;
+        match self {
+            NoFreeSlot(connection) => write!(f, "No free slot for connection {}", connection.0),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Error for MultiParserError {}
+
+/// Collects the bytes of a single message until it is complete.
+///
+/// If a complete message can not be parsed it might have been truncated and the start of the next message is already
+/// in the buffer. In that case the bytes from the next `!` on are replayed, so only the broken message is lost.
+#[derive(Debug, Copy, Clone)]
+struct FrameAssembler {
+    buffer: [u8; MAX_CONTROLLER_MESSAGE_LENGTH],
+    len: usize,
+    command: Option<ControllerDataPackageType>,
+    replay: [u8; MAX_CONTROLLER_MESSAGE_LENGTH],
+    replay_len: usize,
+    replay_pos: usize,
+}
+
+impl FrameAssembler {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; MAX_CONTROLLER_MESSAGE_LENGTH],
+            len: 0,
+            command: None,
+            replay: [0; MAX_CONTROLLER_MESSAGE_LENGTH],
+            replay_len: 0,
+            replay_pos: 0,
+        }
+    }
+
+    /// Discard any partially received message.
+    fn reset(&mut self) {
+        self.clear();
+        self.replay_len = 0;
+        self.replay_pos = 0;
+    }
+
+    /// Discard the message in the buffer.
+    fn clear(&mut self) {
+        self.len = 0;
+        self.command = None;
+    }
+
+    /// The next byte which has to be processed again, see [`FrameAssembler::resync`].
+    fn next_replayed(&mut self) -> Option<u8> {
+        if self.replay_pos < self.replay_len {
+            self.replay_pos += 1;
+            Some(self.replay[self.replay_pos - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Replay the bytes of the broken message in the buffer from the next `!` on (if any), followed by the bytes
+    /// which still had to be replayed.
+    fn resync(&mut self) {
+        let mut replay = [0; MAX_CONTROLLER_MESSAGE_LENGTH];
+        let mut replay_len = 0;
+        if let Some(start) = self.buffer[1..self.len].iter().position(|b| *b == b'!') {
+            let frame = &self.buffer[(start + 1)..self.len];
+            let pending = &self.replay[self.replay_pos..self.replay_len];
+            // the bytes come from the previous replay and the input after it, so they always fit into the buffer
+            replay_len = frame.len() + pending.len();
+            replay[..frame.len()].copy_from_slice(frame);
+            replay[frame.len()..replay_len].copy_from_slice(pending);
+        }
+        if replay_len > 0 {
+            self.replay = replay;
+            self.replay_len = replay_len;
+            self.replay_pos = 0;
+        }
+        self.clear();
+    }
+
+    /// Add the next byte, returns the parsed message once it is complete.
+    fn push(
+        &mut self,
+        byte: u8,
+        options: &ParseOptions,
+    ) -> Option<Result<ControllerEvent, ProtocolParseError>> {
+        match self.command {
+            None if self.len == 0 => {
+                if byte == b'!' {
+                    self.buffer[0] = byte;
+                    self.len = 1;
+                }
+                None
+            }
+            None => {
+                self.clear();
+                match ControllerDataPackageType::try_from(byte) {
+                    Ok(command) => {
+                        self.buffer[1] = byte;
+                        self.len = 2;
+                        self.command = Some(command);
+                        None
+                    }
+                    Err(e) => {
+                        // the start of the next message might directly follow the broken one
+                        if byte == b'!' {
+                            self.buffer[0] = byte;
+                            self.len = 1;
+                        }
+                        Some(Err(e))
+                    }
+                }
+            }
+            Some(command) => {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+                if self.len == command.data_len() + 3 {
+                    let result = parse_command(command, &self.buffer[..self.len], options);
+                    match result {
+                        // the message has been received completely, only its values are implausible
+                        Ok(_) | Err(ProtocolParseError::ValidationError(_)) => self.clear(),
+                        Err(_) => self.resync(),
+                    }
+                    Some(result)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// A slot of the [`MultiParser`], assigned to a connection.
+#[derive(Debug, Copy, Clone)]
+struct Slot {
+    connection: ConnectionId,
+    frame: FrameAssembler,
+}
+
+/// Parses the input of up to `N` connections at the same time.
+///
+/// Each connection gets its own slot which keeps partially received messages, so the input of the connections
+/// can be interleaved arbitrarily. A slot is assigned on the first input of a connection and stays assigned until
+/// [`MultiParser::disconnect`] is called.
+///
+/// If a message has been cut off (e.g. because a packet has been lost), parsing resumes at the start of the next
+/// message within it, so only the broken message is lost.
+///
+/// ## Example
+/// ```
+/// # #[cfg(feature = "button_event")]
+/// # {
+/// # use adafruit_bluefruit_protocol::button_event::{Button, ButtonState};
+/// # use adafruit_bluefruit_protocol::multi_parser::{ConnectionId, MultiParser};
+/// # use adafruit_bluefruit_protocol::ControllerEvent;
+/// let phone_a = ConnectionId(1);
+/// let phone_b = ConnectionId(2);
+/// let mut parser = MultiParser::<2>::new();
+///
+/// // both phones send a message, but they are received in multiple parts
+/// assert_eq!(parser.feed(phone_a, b"!B1").unwrap().count(), 0);
+/// assert_eq!(parser.feed(phone_b, b"!B4").unwrap().count(), 0);
+/// let (connection, event) = parser.feed(phone_a, b"1:").unwrap().next().unwrap();
+/// assert_eq!(connection, phone_a);
+/// match event {
+///     Ok(ControllerEvent::ButtonEvent(event)) => assert_eq!(event.button(), &Button::Button1),
+///     _ => panic!("expected a button event"),
+/// }
+///
+/// // phone B disconnects before sending the rest of the message, the slot can now be used by another phone
+/// parser.disconnect(phone_b);
+/// assert_eq!(parser.feed(ConnectionId(3), b"!B10;").unwrap().count(), 1);
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct MultiParser<const N: usize> {
+    slots: [Option<Slot>; N],
+    options: ParseOptions,
+}
+
+impl<const N: usize> MultiParser<N> {
+    /// Create a new parser without any connections.
+    pub const fn new() -> Self {
+        Self {
+            slots: [None; N],
+            options: ParseOptions::new(),
+        }
+    }
+
+    /// Define how button events are parsed. By default [`ButtonParseMode::Strict`] is used.
+    #[cfg(feature = "button_event")]
+    pub const fn with_button_parse_mode(mut self, mode: ButtonParseMode) -> Self {
+        self.options.button_parse_mode = mode;
+        self
+    }
+
+    /// Reject events with implausible values, see [`validation`](crate::validation). By default no validation is done.
+    pub const fn with_validation(mut self, validation: Validation) -> Self {
+        self.options.validation = Some(validation);
+        self
+    }
+
+    /// Parse the next input received on a connection.
+    ///
+    /// The returned iterator yields all messages which have been completed by this input, incomplete messages are
+    /// kept until the next input of the same connection. If the connection has not been seen before a slot
+    /// is assigned to it, if none is available [`MultiParserError::NoFreeSlot`] is returned.
+    ///
+    /// The input is only parsed while iterating, so the iterator has to be fully drained: if it is dropped early the
+    /// rest of the input is lost, including the start of messages which would be completed by the next input.
+    #[must_use = "the input is only parsed while iterating over the events"]
+    pub fn feed<'a>(
+        &'a mut self,
+        connection: ConnectionId,
+        input: &'a [u8],
+    ) -> Result<MultiParserEvents<'a>, MultiParserError> {
+        let index = match self.slot_index(connection) {
+            Some(index) => index,
+            None => self
+                .slots
+                .iter()
+                .position(Option::is_none)
+                .ok_or(MultiParserError::NoFreeSlot(connection))?,
+        };
+        let slot = self.slots[index].get_or_insert(Slot {
+            connection,
+            frame: FrameAssembler::new(),
+        });
+
+        Ok(MultiParserEvents {
+            connection,
+            frame: &mut slot.frame,
+            options: &self.options,
+            input,
+            curr_pos: 0,
+        })
+    }
+
+    /// Discard a partially received message of the connection (if any). The connection keeps its slot.
+    pub fn reset(&mut self, connection: ConnectionId) {
+        if let Some(Some(slot)) = self
+            .slot_index(connection)
+            .map(|index| &mut self.slots[index])
+        {
+            slot.frame.reset();
+        }
+    }
+
+    /// Free the slot of the connection, discarding any partially received message.
+    ///
+    /// Call this when the connection has been closed.
+    pub fn disconnect(&mut self, connection: ConnectionId) {
+        if let Some(index) = self.slot_index(connection) {
+            self.slots[index] = None;
+        }
+    }
+
+    /// Returns `true` if a slot is currently assigned to the connection.
+    pub fn is_connected(&self, connection: ConnectionId) -> bool {
+        self.slot_index(connection).is_some()
+    }
+
+    fn slot_index(&self, connection: ConnectionId) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| matches!(slot, Some(slot) if slot.connection == connection))
+    }
+}
+
+impl<const N: usize> Default for MultiParser<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the messages completed by an input, see [`MultiParser::feed`].
+///
+/// It has to be fully drained, otherwise the rest of the input is lost.
+#[derive(Debug)]
+#[must_use = "the input is only parsed while iterating over the events"]
+pub struct MultiParserEvents<'a> {
+    connection: ConnectionId,
+    frame: &'a mut FrameAssembler,
+    options: &'a ParseOptions,
+    input: &'a [u8],
+    curr_pos: usize,
+}
+
+impl Iterator for MultiParserEvents<'_> {
+    type Item = (ConnectionId, Result<ControllerEvent, ProtocolParseError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let byte = match self.frame.next_replayed() {
+                Some(byte) => byte,
+                None if self.curr_pos < self.input.len() => {
+                    self.curr_pos += 1;
+                    self.input[self.curr_pos - 1]
+                }
+                None => return None,
+            };
+            if let Some(result) = self.frame.push(byte, self.options) {
+                return Some((self.connection, result));
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "button_event", feature = "color_event"))]
+mod tests {
+    use crate::button_event::{Button, ButtonParseMode, ButtonState};
+    use crate::multi_parser::{ConnectionId, MultiParser, MultiParserError};
+    use crate::{ControllerEvent, ProtocolParseError};
+
+    fn assert_is_button_event(
+        result: Option<(ConnectionId, Result<ControllerEvent, ProtocolParseError>)>,
+        connection: ConnectionId,
+        button: Button,
+        button_state: ButtonState,
+    ) {
+        match result {
+            Some((c, Ok(ControllerEvent::ButtonEvent(event)))) => {
+                assert_eq!(c, connection);
+                assert_eq!(event.button(), &button);
+                assert_eq!(event.state(), &button_state)
+            }
+            _ => panic!("expected a button event"),
+        }
+    }
+
+    #[test]
+    fn test_interleaved_connections() {
+        let a = ConnectionId(1);
+        let b = ConnectionId(2);
+        let mut parser = MultiParser::<2>::new();
+
+        assert_eq!(parser.feed(a, b"\x00\x00!C").unwrap().next(), None);
+        assert_eq!(parser.feed(b, b"!B4").unwrap().next(), None);
+        assert_eq!(parser.feed(a, b"\xff-").unwrap().next(), None);
+        assert_is_button_event(
+            parser.feed(b, b"17").unwrap().next(),
+            b,
+            Button::Button4,
+            ButtonState::Pressed,
+        );
+        match parser.feed(a, b"96").unwrap().next() {
+            Some((c, Ok(ControllerEvent::ColorEvent(event)))) => {
+                assert_eq!(c, a);
+                assert_eq!(event.red(), 255);
+            }
+            _ => panic!("expected a color event"),
+        }
+    }
+
+    #[test]
+    fn test_multiple_events_in_one_input() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new();
+
+        let mut events = parser.feed(a, b"!B11:!B10;!B1").unwrap();
+        assert_is_button_event(events.next(), a, Button::Button1, ButtonState::Pressed);
+        assert_is_button_event(events.next(), a, Button::Button1, ButtonState::Released);
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn test_errors_do_not_affect_following_messages() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new();
+
+        let mut events = parser.feed(a, b"!\x00!B13").unwrap();
+        assert_eq!(
+            events.next(),
+            Some((a, Err(ProtocolParseError::UnknownEvent(Some(0)))))
+        );
+        assert_eq!(events.next(), None);
+        assert!(matches!(
+            parser.feed(a, b"8!B10;").unwrap().next(),
+            Some((_, Err(ProtocolParseError::ButtonParseError(_))))
+        ));
+    }
+
+    #[test]
+    fn test_start_directly_after_broken_message() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new();
+
+        let mut events = parser.feed(a, b"!!B11:").unwrap();
+        assert_eq!(
+            events.next(),
+            Some((a, Err(ProtocolParseError::UnknownEvent(Some(b'!')))))
+        );
+        assert_is_button_event(events.next(), a, Button::Button1, ButtonState::Pressed);
+    }
+
+    #[test]
+    fn test_resync_after_truncated_message() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new();
+
+        // the second byte of the button message is missing, the next message starts within the broken one
+        let mut events = parser.feed(a, b"!B1!B11:").unwrap();
+        assert!(matches!(events.next(), Some((_, Err(_)))));
+        assert_is_button_event(events.next(), a, Button::Button1, ButtonState::Pressed);
+        assert_eq!(events.next(), None);
+
+        // a longer message follows a truncated one, it is received in multiple parts
+        assert!(matches!(
+            parser.feed(a, b"!B1!C").unwrap().next(),
+            Some((_, Err(_)))
+        ));
+        match parser.feed(a, b"\xff-96").unwrap().next() {
+            Some((_, Ok(ControllerEvent::ColorEvent(event)))) => assert_eq!(event.red(), 255),
+            _ => panic!("expected a color event"),
+        }
+
+        // several messages follow a truncated longer one
+        let mut events = parser.feed(a, b"!C\xff!B11:!B10;").unwrap();
+        assert!(matches!(events.next(), Some((_, Err(_)))));
+        assert_is_button_event(events.next(), a, Button::Button1, ButtonState::Pressed);
+        assert_is_button_event(events.next(), a, Button::Button1, ButtonState::Released);
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn test_exclamation_mark_in_data() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new();
+
+        // the green value of the color is `!`
+        let mut events = parser.feed(a, b"!C\x01!\x03v!B11:").unwrap();
+        assert!(matches!(
+            events.next(),
+            Some((_, Ok(ControllerEvent::ColorEvent(_))))
+        ));
+        assert_is_button_event(events.next(), a, Button::Button1, ButtonState::Pressed);
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn test_lenient_button_parse_mode() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new().with_button_parse_mode(ButtonParseMode::Lenient);
+
+        assert_is_button_event(
+            parser.feed(a, b"!B912").unwrap().next(),
+            a,
+            Button::Other(b'9'),
+            ButtonState::Pressed,
+        );
+    }
+
+    #[test]
+    fn test_reset_discards_partial_message() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new();
+
+        assert_eq!(parser.feed(a, b"!B1").unwrap().next(), None);
+        parser.reset(a);
+        assert!(parser.is_connected(a));
+        assert_eq!(parser.feed(a, b"1:").unwrap().next(), None);
+        assert_is_button_event(
+            parser.feed(a, b"!B10;").unwrap().next(),
+            a,
+            Button::Button1,
+            ButtonState::Released,
+        );
+    }
+
+    #[test]
+    fn test_disconnect_frees_slot() {
+        let a = ConnectionId(1);
+        let b = ConnectionId(2);
+        let mut parser = MultiParser::<1>::new();
+
+        assert_eq!(parser.feed(a, b"!B1").unwrap().next(), None);
+        assert_eq!(
+            parser.feed(b, b"!B11:").err(),
+            Some(MultiParserError::NoFreeSlot(b))
+        );
+
+        parser.disconnect(a);
+        assert!(!parser.is_connected(a));
+        assert_is_button_event(
+            parser.feed(b, b"!B11:").unwrap().next(),
+            b,
+            Button::Button1,
+            ButtonState::Pressed,
+        );
+    }
+
+    #[test]
+    fn test_disconnect_discards_partial_message() {
+        let a = ConnectionId(1);
+        let mut parser = MultiParser::<1>::new();
+
+        assert_eq!(parser.feed(a, b"!B1").unwrap().next(), None);
+        parser.disconnect(a);
+        assert_eq!(parser.feed(a, b"1:").unwrap().next(), None);
+    }
+}
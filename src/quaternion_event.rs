@@ -1,11 +1,17 @@
 //! Implements the [`QuaternionEvent`] and its parsing from the protocol.
 
-use super::{try_f32_from_le_bytes, ProtocolParseError};
+use super::text::{TextParseError, Tokens};
+use super::{try_f32_from_le_bytes, ControllerDataPackageType, ProtocolParseError};
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 
 /// Represents a [quaternion](https://en.wikipedia.org/wiki/Quaternion) event from the protocol.
 #[derive(PartialEq, Debug, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[allow(missing_docs)] // the names are already obvious enough
 pub struct QuaternionEvent {
     x: f32,
@@ -35,8 +41,63 @@ impl TryFrom<&[u8]> for QuaternionEvent {
     }
 }
 
+impl FromStr for QuaternionEvent {
+    type Err = TextParseError;
+
+    /// Parse the text form of the event (e.g. `quat w=1.0 x=0.0 y=0.0 z=0.0`), see [`text`](super::text).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Tokens::new(s);
+        tokens.expect_event(ControllerDataPackageType::Quaternion)?;
+        let w = tokens.named_f32("w")?;
+        let event = QuaternionEvent {
+            x: tokens.named_f32("x")?,
+            y: tokens.named_f32("y")?,
+            z: tokens.named_f32("z")?,
+            w,
+        };
+        tokens.end()?;
+        Ok(event)
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Display for QuaternionEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "quat w={:?} x={:?} y={:?} z={:?}",
+            self.w, self.x, self.y, self.z
+        )
+    }
+}
+
 #[allow(missing_docs)] // the names are already obvious enough
 impl QuaternionEvent {
+    /// Create the event, e.g. `QuaternionEvent::new(0.0, 0.0, 0.0, 1.0)`.
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    pub const fn with_x(mut self, x: f32) -> Self {
+        self.x = x;
+        self
+    }
+
+    pub const fn with_y(mut self, y: f32) -> Self {
+        self.y = y;
+        self
+    }
+
+    pub const fn with_z(mut self, z: f32) -> Self {
+        self.z = z;
+        self
+    }
+
+    pub const fn with_w(mut self, w: f32) -> Self {
+        self.w = w;
+        self
+    }
+
     pub fn x(&self) -> f32 {
         self.x
     }
@@ -70,4 +131,47 @@ mod tests {
 
         assert_eq!(QuaternionEvent::try_from(input), Ok(expected));
     }
+
+    #[test]
+    fn test_text_form() {
+        let text = "quat w=1.0 x=0.0 y=-0.81627196 z=0.29387614";
+        let event = QuaternionEvent {
+            x: 0.0,
+            y: -0.81627196,
+            z: 0.29387614,
+            w: 1.0,
+        };
+
+        assert_eq!(text.parse(), Ok(event));
+        assert_eq!("quat w=1 x=0 y=-0.81627196 z=0.29387614".parse(), Ok(event));
+        #[cfg(feature = "fmt")]
+        {
+            extern crate alloc;
+            use alloc::string::ToString;
+            assert_eq!(event.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_invalid_text_form() {
+        use crate::text::TextParseError;
+
+        // the values must be given in the documented order
+        assert_eq!(
+            "quat x=0 y=0 z=0 w=1".parse::<QuaternionEvent>(),
+            Err(TextParseError::InvalidValue(1))
+        );
+    }
+
+    #[test]
+    fn test_new() {
+        let event = QuaternionEvent::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!(
+            (event.x(), event.y(), event.z(), event.w()),
+            (1.0, 2.0, 3.0, 4.0)
+        );
+
+        let event = event.with_x(-1.0).with_y(-2.0).with_z(-3.0).with_w(-4.0);
+        assert_eq!(event, QuaternionEvent::new(-1.0, -2.0, -3.0, -4.0));
+    }
 }
@@ -0,0 +1,315 @@
+//! Implements the [`ControllerSnapshot`] which keeps the latest value of each input of the controller.
+
+#[cfg(feature = "accelerometer_event")]
+use super::accelerometer_event::AccelerometerEvent;
+#[cfg(feature = "button_event")]
+use super::button_event::{Button, ButtonState};
+#[cfg(feature = "color_event")]
+use super::color_event::ColorEvent;
+#[cfg(feature = "gyro_event")]
+use super::gyro_event::GyroEvent;
+#[cfg(feature = "location_event")]
+use super::location_event::LocationEvent;
+#[cfg(feature = "magnetometer_event")]
+use super::magnetometer_event::MagnetometerEvent;
+#[cfg(feature = "quaternion_event")]
+use super::quaternion_event::QuaternionEvent;
+use super::{ControllerEvent, ProtocolParseError};
+
+/// A value stored in the [`ControllerSnapshot`] together with the sequence number of the event which set it.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Sample<T> {
+    value: T,
+    sequence: u32,
+}
+
+impl<T> Sample<T> {
+    /// The latest value.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// The sequence number of the event which set the value, see [`ControllerSnapshot::sequence`].
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+}
+
+/// Number of buttons for which the state is tracked, see [`ControllerSnapshot::button`].
+#[cfg(feature = "button_event")]
+const BUTTON_COUNT: usize = Button::KNOWN.len();
+
+/// Keeps the latest value of each input of the controller.
+///
+/// Use this if you are only interested in the current state and not in the individual events.
+/// Each value is stored as a [`Sample`] which contains the sequence number of the event which set it, this allows
+/// you to see how many events have been received since (see [`ControllerSnapshot::age`]).
+///
+/// The snapshot is `Copy`, so it can e.g. be shared between the task receiving the data and a control loop
+/// using a mutex where the control loop just copies the snapshot out.
+///
+/// ## Example
+/// ```
+/// # #[cfg(all(feature = "button_event", feature = "color_event"))]
+/// # {
+/// # use adafruit_bluefruit_protocol::button_event::{Button, ButtonState};
+/// # use adafruit_bluefruit_protocol::snapshot::ControllerSnapshot;
+/// # use adafruit_bluefruit_protocol::Parser;
+/// let mut snapshot = ControllerSnapshot::new();
+/// snapshot.apply_all(Parser::new(b"!B11:!C\xff-96!B219"));
+///
+/// assert_eq!(snapshot.sequence(), 3);
+/// assert_eq!(snapshot.color().map(|c| c.value().red()), Some(255));
+/// let button1 = snapshot.button(Button::Button1).unwrap();
+/// assert_eq!(button1.value(), &ButtonState::Pressed);
+/// assert_eq!(snapshot.age(button1), 2);
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ControllerSnapshot {
+    sequence: u32,
+    #[cfg(feature = "button_event")]
+    buttons: [Option<Sample<ButtonState>>; BUTTON_COUNT],
+    #[cfg(feature = "color_event")]
+    color: Option<Sample<ColorEvent>>,
+    #[cfg(feature = "quaternion_event")]
+    quaternion: Option<Sample<QuaternionEvent>>,
+    #[cfg(feature = "accelerometer_event")]
+    accelerometer: Option<Sample<AccelerometerEvent>>,
+    #[cfg(feature = "gyro_event")]
+    gyro: Option<Sample<GyroEvent>>,
+    #[cfg(feature = "magnetometer_event")]
+    magnetometer: Option<Sample<MagnetometerEvent>>,
+    #[cfg(feature = "location_event")]
+    location: Option<Sample<LocationEvent>>,
+}
+
+impl ControllerSnapshot {
+    /// Create an empty snapshot.
+    pub const fn new() -> Self {
+        Self {
+            sequence: 0,
+            #[cfg(feature = "button_event")]
+            buttons: [None; BUTTON_COUNT],
+            #[cfg(feature = "color_event")]
+            color: None,
+            #[cfg(feature = "quaternion_event")]
+            quaternion: None,
+            #[cfg(feature = "accelerometer_event")]
+            accelerometer: None,
+            #[cfg(feature = "gyro_event")]
+            gyro: None,
+            #[cfg(feature = "magnetometer_event")]
+            magnetometer: None,
+            #[cfg(feature = "location_event")]
+            location: None,
+        }
+    }
+
+    /// Update the snapshot with the event.
+    ///
+    /// Events for [`Button::Other`] are counted but otherwise ignored.
+    pub fn apply(&mut self, event: &ControllerEvent) {
+        self.sequence = self.sequence.wrapping_add(1);
+        let sequence = self.sequence;
+
+        match event {
+            #[cfg(feature = "button_event")]
+            ControllerEvent::ButtonEvent(event) => {
+                if let Some(index) = event.button().index() {
+                    self.buttons[index] = Some(Sample {
+                        value: *event.state(),
+                        sequence,
+                    });
+                }
+            }
+            #[cfg(feature = "color_event")]
+            ControllerEvent::ColorEvent(event) => {
+                self.color = Some(Sample {
+                    value: *event,
+                    sequence,
+                })
+            }
+            #[cfg(feature = "quaternion_event")]
+            ControllerEvent::QuaternionEvent(event) => {
+                self.quaternion = Some(Sample {
+                    value: *event,
+                    sequence,
+                })
+            }
+            #[cfg(feature = "accelerometer_event")]
+            ControllerEvent::AccelerometerEvent(event) => {
+                self.accelerometer = Some(Sample {
+                    value: *event,
+                    sequence,
+                })
+            }
+            #[cfg(feature = "gyro_event")]
+            ControllerEvent::GyroEvent(event) => {
+                self.gyro = Some(Sample {
+                    value: *event,
+                    sequence,
+                })
+            }
+            #[cfg(feature = "magnetometer_event")]
+            ControllerEvent::MagnetometerEvent(event) => {
+                self.magnetometer = Some(Sample {
+                    value: *event,
+                    sequence,
+                })
+            }
+            #[cfg(feature = "location_event")]
+            ControllerEvent::LocationEvent(event) => {
+                self.location = Some(Sample {
+                    value: *event,
+                    sequence,
+                })
+            }
+        }
+    }
+
+    /// Update the snapshot with all events from the parser. Errors are skipped, their number is returned.
+    pub fn apply_all<I>(&mut self, events: I) -> usize
+    where
+        I: IntoIterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+    {
+        let mut errors = 0;
+        for event in events {
+            match event {
+                Ok(event) => self.apply(&event),
+                Err(_) => errors += 1,
+            }
+        }
+        errors
+    }
+
+    /// The sequence number of the last applied event, i.e. the number of events applied so far.
+    ///
+    /// Note that this wraps around after [`u32::MAX`] events.
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// The number of events which have been applied since the sample has been set, `0` if it has been set by the last event.
+    pub fn age<T>(&self, sample: &Sample<T>) -> u32 {
+        self.sequence.wrapping_sub(sample.sequence)
+    }
+
+    /// The latest state of the button, `None` if no event has been received for it (or for [`Button::Other`]).
+    #[cfg(feature = "button_event")]
+    pub fn button(&self, button: Button) -> Option<&Sample<ButtonState>> {
+        button
+            .index()
+            .and_then(|index| self.buttons[index].as_ref())
+    }
+
+    /// The latest [`ColorEvent`].
+    #[cfg(feature = "color_event")]
+    pub fn color(&self) -> Option<&Sample<ColorEvent>> {
+        self.color.as_ref()
+    }
+
+    /// The latest [`QuaternionEvent`].
+    #[cfg(feature = "quaternion_event")]
+    pub fn quaternion(&self) -> Option<&Sample<QuaternionEvent>> {
+        self.quaternion.as_ref()
+    }
+
+    /// The latest [`AccelerometerEvent`].
+    #[cfg(feature = "accelerometer_event")]
+    pub fn accelerometer(&self) -> Option<&Sample<AccelerometerEvent>> {
+        self.accelerometer.as_ref()
+    }
+
+    /// The latest [`GyroEvent`].
+    #[cfg(feature = "gyro_event")]
+    pub fn gyro(&self) -> Option<&Sample<GyroEvent>> {
+        self.gyro.as_ref()
+    }
+
+    /// The latest [`MagnetometerEvent`].
+    #[cfg(feature = "magnetometer_event")]
+    pub fn magnetometer(&self) -> Option<&Sample<MagnetometerEvent>> {
+        self.magnetometer.as_ref()
+    }
+
+    /// The latest [`LocationEvent`].
+    #[cfg(feature = "location_event")]
+    pub fn location(&self) -> Option<&Sample<LocationEvent>> {
+        self.location.as_ref()
+    }
+}
+
+impl Default for ControllerSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "button_event", feature = "color_event"))]
+mod tests {
+    use crate::button_event::{Button, ButtonParseMode, ButtonState};
+    use crate::snapshot::ControllerSnapshot;
+    use crate::Parser;
+
+    #[test]
+    fn test_empty_snapshot() {
+        let snapshot = ControllerSnapshot::new();
+
+        assert_eq!(snapshot.sequence(), 0);
+        assert_eq!(snapshot.button(Button::Button1), None);
+        assert_eq!(snapshot.color(), None);
+    }
+
+    #[test]
+    fn test_latest_value_wins() {
+        let mut snapshot = ControllerSnapshot::new();
+        let errors = snapshot.apply_all(Parser::new(b"!B11:!B138!B10;!C\x01\x02\x03\x95"));
+
+        assert_eq!(errors, 1);
+        assert_eq!(snapshot.sequence(), 3);
+
+        let button1 = snapshot.button(Button::Button1).unwrap();
+        assert_eq!(button1.value(), &ButtonState::Released);
+        assert_eq!(button1.sequence(), 2);
+        assert_eq!(snapshot.age(button1), 1);
+        assert_eq!(snapshot.button(Button::Button2), None);
+
+        let color = snapshot.color().unwrap();
+        assert_eq!(color.value().blue(), 3);
+        assert_eq!(snapshot.age(color), 0);
+    }
+
+    #[test]
+    fn test_other_buttons_are_ignored() {
+        let mut snapshot = ControllerSnapshot::new();
+        snapshot.apply_all(Parser::new(b"!B912").with_button_parse_mode(ButtonParseMode::Lenient));
+
+        assert_eq!(snapshot.sequence(), 1);
+        assert_eq!(snapshot.button(Button::Other(b'9')), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip() {
+        let mut snapshot = ControllerSnapshot::new();
+        snapshot.apply_all(Parser::new(b"!B11:!C\xff-96"));
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ControllerSnapshot>(&json).unwrap(),
+            snapshot
+        );
+        let bytes = postcard::to_allocvec(&snapshot).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<ControllerSnapshot>(&bytes).unwrap(),
+            snapshot
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Helpers to build the input for tests of code which uses the [`Parser`](crate::Parser).
+//!
+//! Each [`TestFrame`] contains the bytes of a (valid or malformed) message together with the result which the
+//! [`Parser`](crate::Parser) returns for it. The malformed messages are derived from valid ones, so you do not have to
+//! write them (and their CRC) by hand.
+//!
+//! The expected results are the ones of the parser with the default options, e.g. a `Button::Other` with an unknown
+//! ID results in a `ButtonParseError::UnknownButton`. Note that events containing a NaN value never compare equal,
+//! not even to themselves.
+//!
+//! This module needs an allocator, which is usually available when running tests.
+//!
+//! ## Example
+//! ```
+//! # use adafruit_bluefruit_protocol::button_event::{Button, ButtonState};
+//! # use adafruit_bluefruit_protocol::test_utils::{button_event, flipped_crc};
+//! # use adafruit_bluefruit_protocol::{Parser, ProtocolParseError};
+//! let frame = flipped_crc(&button_event(Button::Button1, ButtonState::Pressed));
+//! assert_eq!(frame.bytes(), b"!B11\xc5");
+//! assert_eq!(frame.expected(), &Err(ProtocolParseError::InvalidCrc(0xc5, 0x3a)));
+//! assert_eq!(Parser::new(frame.bytes()).next().as_ref(), Some(frame.expected()));
+//! ```
+
+extern crate alloc;
+
+#[cfg(feature = "accelerometer_event")]
+use super::accelerometer_event::AccelerometerEvent;
+#[cfg(feature = "button_event")]
+use super::button_event::{Button, ButtonEvent, ButtonState};
+#[cfg(feature = "color_event")]
+use super::color_event::ColorEvent;
+#[cfg(feature = "gyro_event")]
+use super::gyro_event::GyroEvent;
+#[cfg(feature = "location_event")]
+use super::location_event::LocationEvent;
+#[cfg(feature = "magnetometer_event")]
+use super::magnetometer_event::MagnetometerEvent;
+#[cfg(feature = "quaternion_event")]
+use super::quaternion_event::QuaternionEvent;
+use super::{ControllerEvent, ProtocolParseError};
+use alloc::vec::Vec;
+
+/// The bytes of a message together with the first result the [`Parser`](crate::Parser) returns for them.
+///
+/// Note that the parser searches for the next message right after the start of an invalid message, so depending on
+/// the content of the malformed message further errors can follow.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TestFrame {
+    bytes: Vec<u8>,
+    expected: Result<ControllerEvent, ProtocolParseError>,
+}
+
+impl TestFrame {
+    /// The bytes to feed to the parser.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The first result of the parser.
+    pub fn expected(&self) -> &Result<ControllerEvent, ProtocolParseError> {
+        &self.expected
+    }
+}
+
+/// The bytes of several messages together with all results the [`Parser`](crate::Parser) returns for them.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TestFrames {
+    bytes: Vec<u8>,
+    expected: Vec<Result<ControllerEvent, ProtocolParseError>>,
+}
+
+impl TestFrames {
+    /// The bytes to feed to the parser.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// All results of the parser, in order.
+    pub fn expected(&self) -> &[Result<ControllerEvent, ProtocolParseError>] {
+        &self.expected
+    }
+}
+
+/// The result of the parser for the valid message of the event.
+fn parsed(event: &ControllerEvent) -> Result<ControllerEvent, ProtocolParseError> {
+    #[allow(irrefutable_let_patterns)] // depends on the selected events
+    #[cfg(feature = "button_event")]
+    if let ControllerEvent::ButtonEvent(button_event) = event {
+        if let Button::Other(id) = button_event.button() {
+            // only known buttons are accepted by the parser in strict mode
+            return Button::from_id(id)
+                .map(|button| {
+                    ControllerEvent::ButtonEvent(ButtonEvent::new(button, *button_event.state()))
+                })
+                .map_err(ProtocolParseError::ButtonParseError);
+        }
+    }
+    Ok(*event)
+}
+
+/// The valid message of the event.
+pub fn valid(event: &ControllerEvent) -> TestFrame {
+    TestFrame {
+        bytes: event.to_frame().as_bytes().to_vec(),
+        expected: parsed(event),
+    }
+}
+
+/// The message of the event with an inverted CRC.
+pub fn flipped_crc(event: &ControllerEvent) -> TestFrame {
+    let mut bytes = event.to_frame().as_bytes().to_vec();
+    let crc = bytes.pop().unwrap();
+    bytes.push(!crc);
+    TestFrame {
+        bytes,
+        expected: Err(ProtocolParseError::InvalidCrc(!crc, crc as u16)),
+    }
+}
+
+/// The first `len` bytes of the message of the event.
+///
+/// # Panics
+/// If `len` is less than 2 (the parser ignores a lone `!`) or not less than the length of the message.
+pub fn truncated(event: &ControllerEvent, len: usize) -> TestFrame {
+    let frame = event.to_frame();
+    let frame_len = frame.as_bytes().len();
+    assert!(
+        (2..frame_len).contains(&len),
+        "the length must be in 2..{}",
+        frame_len
+    );
+    TestFrame {
+        bytes: frame.as_bytes()[..len].to_vec(),
+        expected: Err(ProtocolParseError::InvalidLength(frame_len, len)),
+    }
+}
+
+/// The valid message of the event with NUL bytes before and after it, the parser skips them.
+pub fn nul_padded(event: &ControllerEvent, before: usize, after: usize) -> TestFrame {
+    let mut bytes = alloc::vec![0; before];
+    bytes.extend_from_slice(event.to_frame().as_bytes());
+    bytes.resize(bytes.len() + after, 0);
+    TestFrame {
+        bytes,
+        expected: parsed(event),
+    }
+}
+
+/// The message of the event with a `!` inserted before the CRC, as if the start of the next message had been received
+/// too early.
+pub fn embedded_start(event: &ControllerEvent) -> TestFrame {
+    let mut bytes = event.to_frame().as_bytes().to_vec();
+    let crc = bytes[bytes.len() - 1];
+    bytes.insert(bytes.len() - 1, b'!');
+    TestFrame {
+        bytes,
+        // the `!` is now at the position of the CRC
+        expected: if crc == b'!' {
+            parsed(event)
+        } else {
+            Err(ProtocolParseError::InvalidCrc(b'!', crc as u16))
+        },
+    }
+}
+
+/// The valid messages of all events, one after the other. The parser returns exactly these events (unless they are
+/// rejected, like a `Button::Other` with an unknown ID).
+pub fn concatenated(events: &[ControllerEvent]) -> TestFrames {
+    TestFrames {
+        bytes: events
+            .iter()
+            .flat_map(|event| event.to_frame().as_bytes().to_vec())
+            .collect(),
+        expected: events.iter().map(parsed).collect(),
+    }
+}
+
+/// Shorthand to create a [`ControllerEvent::ButtonEvent`].
+#[cfg(feature = "button_event")]
+pub fn button_event(button: Button, state: ButtonState) -> ControllerEvent {
+    ControllerEvent::ButtonEvent(ButtonEvent::new(button, state))
+}
+
+/// Shorthand to create a [`ControllerEvent::ColorEvent`].
+#[cfg(feature = "color_event")]
+pub fn color_event(red: u8, green: u8, blue: u8) -> ControllerEvent {
+    ControllerEvent::ColorEvent(ColorEvent::new(red, green, blue))
+}
+
+/// Shorthand to create a [`ControllerEvent::QuaternionEvent`].
+#[cfg(feature = "quaternion_event")]
+pub fn quaternion_event(x: f32, y: f32, z: f32, w: f32) -> ControllerEvent {
+    ControllerEvent::QuaternionEvent(QuaternionEvent::new(x, y, z, w))
+}
+
+/// Shorthand to create a [`ControllerEvent::AccelerometerEvent`].
+#[cfg(feature = "accelerometer_event")]
+pub fn accelerometer_event(x: f32, y: f32, z: f32) -> ControllerEvent {
+    ControllerEvent::AccelerometerEvent(AccelerometerEvent::new(x, y, z))
+}
+
+/// Shorthand to create a [`ControllerEvent::GyroEvent`].
+#[cfg(feature = "gyro_event")]
+pub fn gyro_event(x: f32, y: f32, z: f32) -> ControllerEvent {
+    ControllerEvent::GyroEvent(GyroEvent::new(x, y, z))
+}
+
+/// Shorthand to create a [`ControllerEvent::MagnetometerEvent`].
+#[cfg(feature = "magnetometer_event")]
+pub fn magnetometer_event(x: f32, y: f32, z: f32) -> ControllerEvent {
+    ControllerEvent::MagnetometerEvent(MagnetometerEvent::new(x, y, z))
+}
+
+/// Shorthand to create a [`ControllerEvent::LocationEvent`].
+#[cfg(feature = "location_event")]
+pub fn location_event(latitude: f32, longitude: f32, altitude: f32) -> ControllerEvent {
+    ControllerEvent::LocationEvent(LocationEvent::new(latitude, longitude, altitude))
+}
+
+#[cfg(all(
+    test,
+    feature = "button_event",
+    feature = "color_event",
+    feature = "gyro_event"
+))]
+mod tests {
+    use super::*;
+    use crate::Parser;
+
+    fn events() -> [ControllerEvent; 4] {
+        [
+            button_event(Button::Up, ButtonState::Released),
+            color_event(1, b'!', 3),
+            // the CRC of this event is `!`
+            color_event(0x7a, 0, 0),
+            gyro_event(0.5, -1.0, 1e10),
+        ]
+    }
+
+    fn assert_first_result(frame: &TestFrame) {
+        assert_eq!(
+            Parser::new(frame.bytes()).next().as_ref(),
+            Some(frame.expected()),
+            "{:?}",
+            frame.bytes()
+        );
+    }
+
+    #[test]
+    fn test_valid() {
+        for event in events() {
+            let frame = valid(&event);
+            assert_first_result(&frame);
+            assert_eq!(Parser::new(frame.bytes()).count(), 1);
+        }
+        assert_eq!(valid(&color_event(0x7a, 0, 0)).bytes(), b"!C\x7a\x00\x00!");
+    }
+
+    #[test]
+    fn test_valid_other_button() {
+        let frame = valid(&button_event(Button::Other(b'9'), ButtonState::Pressed));
+        assert_eq!(
+            frame.expected(),
+            &Err(ProtocolParseError::ButtonParseError(
+                crate::button_event::ButtonParseError::UnknownButton(b'9')
+            ))
+        );
+        assert_first_result(&frame);
+
+        let frame = valid(&button_event(Button::Other(b'1'), ButtonState::Pressed));
+        assert_eq!(
+            frame.expected(),
+            &Ok(button_event(Button::Button1, ButtonState::Pressed))
+        );
+        assert_first_result(&frame);
+    }
+
+    #[test]
+    fn test_malformed() {
+        for event in events() {
+            assert_first_result(&flipped_crc(&event));
+            assert_first_result(&embedded_start(&event));
+            assert_first_result(&nul_padded(&event, 3, 2));
+            for len in 2..event.to_frame().as_bytes().len() {
+                assert_first_result(&truncated(&event, len));
+            }
+        }
+        assert_eq!(
+            embedded_start(&color_event(0x7a, 0, 0)).expected(),
+            &Ok(color_event(0x7a, 0, 0))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_truncated_to_full_length() {
+        truncated(&button_event(Button::Up, ButtonState::Released), 5);
+    }
+
+    #[test]
+    fn test_concatenated() {
+        let events = events();
+        let frames = concatenated(&events);
+
+        let parsed: Vec<_> = Parser::new(frames.bytes()).collect();
+        assert_eq!(parsed, frames.expected());
+        assert_eq!(frames.expected().len(), events.len());
+    }
+}
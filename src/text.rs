@@ -0,0 +1,213 @@
+//! Implements the human-readable text form of the events.
+//!
+//! All events implement [`Display`](core::fmt::Display) (with the `fmt` feature) and [`FromStr`](core::str::FromStr)
+//! using the following syntax, where the values are separated by (any amount of) whitespace:
+//!
+//! | Event                  | Text                                                        |
+//! |------------------------|-------------------------------------------------------------|
+//! | button event           | `button 1 pressed`, `button up released`, `button 0x39 pressed` |
+//! | color event            | `color #ff2d39`                                             |
+//! | quaternion event       | `quat w=1.0 x=0.0 y=0.0 z=0.0`                              |
+//! | accelerometer event    | `accel x=0.1 y=-0.2 z=9.81`                                 |
+//! | gyro event             | `gyro x=0.1 y=-0.2 z=0.3`                                   |
+//! | magnetometer event     | `mag x=10.5 y=-20.0 z=30.25`                                |
+//! | location event         | `location lat=47.37 lon=8.54 alt=408.0`                     |
+//!
+//! The values must be given in the order shown above. The floats are written with as many digits as are needed to parse
+//! them back to the same value, so the text form round-trips without any loss.
+//!
+//! ## Example
+//! ```
+//! # use adafruit_bluefruit_protocol::ControllerEvent;
+//! # #[cfg(feature = "color_event")]
+//! # {
+//! let event: ControllerEvent = "color #ff2d39".parse().unwrap();
+//! assert_eq!(event.to_frame().as_bytes(), b"!C\xff-96");
+//! # #[cfg(feature = "fmt")]
+//! # {
+//! #     extern crate alloc;
+//! #     use alloc::string::ToString;
+//! assert_eq!(event.to_string(), "color #ff2d39");
+//! # }
+//! # }
+//! ```
+
+use super::ControllerDataPackageType;
+#[cfg(feature = "fmt")]
+use core::error::Error;
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+use core::str::SplitAsciiWhitespace;
+
+/// Errors which can be raised while parsing the text form of an event.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum TextParseError {
+    /// The text does not start with the name of a known event.
+    UnknownEvent,
+    /// The event is known to the library but has not been selected as a feature.
+    DisabledControllerDataPackageType(ControllerDataPackageType),
+    /// The text does not start with the name of the expected event.
+    UnexpectedEvent,
+    /// A value is missing or does not have the expected form. The parameter is the position of the value (starting at `0` for the event name).
+    InvalidValue(usize),
+    /// The text contains more values than the event has.
+    TrailingInput,
+}
+
+#[cfg(feature = "fmt")]
+impl Display for TextParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        use TextParseError::*;
+        match self {
+            UnknownEvent => write!(f, "Unknown event name"),
+            DisabledControllerDataPackageType(event) => {
+                write!(f, "Disabled event type: {:?}", event)
+            }
+            UnexpectedEvent => write!(f, "Unexpected event name"),
+            InvalidValue(position) => {
+                write!(f, "Missing or invalid value at position {}", position)
+            }
+            TrailingInput => write!(f, "Unexpected values after the end of the event"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Error for TextParseError {}
+
+/// The name of the event in the text form.
+fn event_name(data_package_type: ControllerDataPackageType) -> &'static str {
+    match data_package_type {
+        ControllerDataPackageType::ButtonCommand => "button",
+        ControllerDataPackageType::Color => "color",
+        ControllerDataPackageType::Quaternion => "quat",
+        ControllerDataPackageType::Accelerometer => "accel",
+        ControllerDataPackageType::Gyro => "gyro",
+        ControllerDataPackageType::Magnetometer => "mag",
+        ControllerDataPackageType::Location => "location",
+    }
+}
+
+/// Splits the text form of an event into its values.
+#[derive(Debug, Clone)]
+pub(crate) struct Tokens<'a> {
+    tokens: SplitAsciiWhitespace<'a>,
+    position: usize,
+}
+
+#[allow(unused)] // not all functions are used by all events
+impl<'a> Tokens<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        Self {
+            tokens: text.split_ascii_whitespace(),
+            position: 0,
+        }
+    }
+
+    /// The name of the event, i.e. the first value.
+    pub(crate) fn event(&mut self) -> Result<ControllerDataPackageType, TextParseError> {
+        let name = self.tokens.next().ok_or(TextParseError::UnknownEvent)?;
+        self.position = 1;
+        [
+            ControllerDataPackageType::ButtonCommand,
+            ControllerDataPackageType::Color,
+            ControllerDataPackageType::Quaternion,
+            ControllerDataPackageType::Accelerometer,
+            ControllerDataPackageType::Gyro,
+            ControllerDataPackageType::Magnetometer,
+            ControllerDataPackageType::Location,
+        ]
+        .into_iter()
+        .find(|t| event_name(*t) == name)
+        .ok_or(TextParseError::UnknownEvent)
+    }
+
+    /// Consume the name of the event, it must be the expected one.
+    pub(crate) fn expect_event(
+        &mut self,
+        data_package_type: ControllerDataPackageType,
+    ) -> Result<(), TextParseError> {
+        if self.event()? == data_package_type {
+            Ok(())
+        } else {
+            Err(TextParseError::UnexpectedEvent)
+        }
+    }
+
+    /// The next value.
+    pub(crate) fn next(&mut self) -> Result<&'a str, TextParseError> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or(TextParseError::InvalidValue(self.position))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// The error for the value which has been returned last.
+    pub(crate) fn invalid(&self) -> TextParseError {
+        TextParseError::InvalidValue(self.position - 1)
+    }
+
+    /// The next value in the form `name=value`.
+    pub(crate) fn named_f32(&mut self, name: &str) -> Result<f32, TextParseError> {
+        let token = self.next()?;
+        token
+            .strip_prefix(name)
+            .and_then(|value| value.strip_prefix('='))
+            .and_then(|value| value.parse().ok())
+            .ok_or(self.invalid())
+    }
+
+    /// Make sure that all values have been consumed.
+    pub(crate) fn end(mut self) -> Result<(), TextParseError> {
+        match self.tokens.next() {
+            None => Ok(()),
+            Some(_) => Err(TextParseError::TrailingInput),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text::{TextParseError, Tokens};
+    use crate::ControllerDataPackageType;
+
+    #[test]
+    fn test_tokens() {
+        let mut tokens = Tokens::new("  gyro\tx=1.5   y=-2 ");
+
+        assert_eq!(tokens.event(), Ok(ControllerDataPackageType::Gyro));
+        assert_eq!(tokens.named_f32("x"), Ok(1.5));
+        assert_eq!(tokens.named_f32("z"), Err(TextParseError::InvalidValue(2)));
+        assert_eq!(tokens.named_f32("z"), Err(TextParseError::InvalidValue(3)));
+        assert_eq!(tokens.end(), Ok(()));
+    }
+
+    #[test]
+    fn test_unknown_event() {
+        assert_eq!(Tokens::new("").event(), Err(TextParseError::UnknownEvent));
+        assert_eq!(
+            Tokens::new("buttons 1 pressed").event(),
+            Err(TextParseError::UnknownEvent)
+        );
+        assert_eq!(
+            Tokens::new("color #000000").expect_event(ControllerDataPackageType::Quaternion),
+            Err(TextParseError::UnexpectedEvent)
+        );
+    }
+
+    #[test]
+    fn test_trailing_input() {
+        let mut tokens = Tokens::new("accel x=1 y=2 z=3 w=4");
+        tokens.event().unwrap();
+        tokens.named_f32("x").unwrap();
+        tokens.named_f32("y").unwrap();
+        tokens.named_f32("z").unwrap();
+
+        assert_eq!(tokens.end(), Err(TextParseError::TrailingInput));
+    }
+}
@@ -0,0 +1,383 @@
+//! Implements the [`Throttle`] which reduces high-rate sensor streams to what the application can use.
+
+use super::time::{Clock, Duration, Instant};
+use super::{
+    abs_f32, ControllerDataPackageType, ControllerEvent, ProtocolParseError,
+    DATA_PACKAGE_TYPE_COUNT,
+};
+
+/// The throttling settings of a single event type.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Settings {
+    interval: Duration,
+    deadband: f32,
+}
+
+/// The state of a single event type.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct State {
+    last_emitted: Option<(Instant, ControllerEvent)>,
+    pending: Option<ControllerEvent>,
+}
+
+/// Limits the rate of the events per event type and suppresses insignificant changes.
+///
+/// For each event type you can configure:
+/// * an interval: at most one event is emitted per interval. Events received in between are not lost: the latest of them
+///   is emitted once the interval has elapsed (either at the end of the next [`Throttle::filter`] or by [`Throttle::poll`]).
+/// * a deadband: events are suppressed if none of their values differs by at least the deadband from the last emitted event.
+///
+/// Button events and errors always pass through unchanged so that e.g. a button release is never lost.
+/// By default, nothing is throttled.
+///
+/// ## Example
+/// ```
+/// # use adafruit_bluefruit_protocol::throttle::Throttle;
+/// # use adafruit_bluefruit_protocol::time::{Duration, Instant};
+/// # use adafruit_bluefruit_protocol::{ControllerDataPackageType, Parser};
+/// # use core::cell::Cell;
+/// # #[cfg(feature = "color_event")]
+/// # {
+/// let now = Cell::new(Instant::from_millis(0));
+/// let clock = || now.get();
+/// let mut throttle = Throttle::new()
+///     .with_interval(ControllerDataPackageType::Color, Duration::from_millis(100));
+///
+/// // only the first of the two colors is emitted, the button event always passes
+/// let input = b"!C\xff-96!B11:!C\x01\x02\x03\x95";
+/// assert_eq!(throttle.filter(Parser::new(input), &clock).count(), 2);
+///
+/// // the second color is emitted once the interval has elapsed
+/// now.set(Instant::from_millis(100));
+/// assert!(throttle.poll(now.get()).is_some());
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Throttle {
+    settings: [Settings; DATA_PACKAGE_TYPE_COUNT],
+    states: [State; DATA_PACKAGE_TYPE_COUNT],
+}
+
+impl Throttle {
+    /// Create a throttle which does not throttle anything, use the `with_*` functions to configure it.
+    pub const fn new() -> Self {
+        Self {
+            settings: [Settings {
+                interval: Duration::ZERO,
+                deadband: 0.0,
+            }; DATA_PACKAGE_TYPE_COUNT],
+            states: [State {
+                last_emitted: None,
+                pending: None,
+            }; DATA_PACKAGE_TYPE_COUNT],
+        }
+    }
+
+    /// Emit at most one event of the type per interval. This has no effect for [`ControllerDataPackageType::ButtonCommand`].
+    pub const fn with_interval(
+        mut self,
+        data_package_type: ControllerDataPackageType,
+        interval: Duration,
+    ) -> Self {
+        self.settings[data_package_type as usize].interval = interval;
+        self
+    }
+
+    /// Suppress events of the type if none of their values differs by at least `deadband` from the last emitted event.
+    /// This has no effect for [`ControllerDataPackageType::ButtonCommand`].
+    pub const fn with_deadband(
+        mut self,
+        data_package_type: ControllerDataPackageType,
+        deadband: f32,
+    ) -> Self {
+        self.settings[data_package_type as usize].deadband = deadband;
+        self
+    }
+
+    /// Throttle the events, the current time is taken from the clock.
+    ///
+    /// At the end of the input all events which are due are emitted as well (as with [`Throttle::poll`]).
+    pub fn filter<'a, I, C>(
+        &'a mut self,
+        events: I,
+        clock: &'a C,
+    ) -> ThrottledEvents<'a, I::IntoIter, C>
+    where
+        I: IntoIterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+        C: Clock,
+    {
+        ThrottledEvents {
+            events: events.into_iter(),
+            clock,
+            throttle: self,
+        }
+    }
+
+    /// Returns the next event which has been held back and whose interval has elapsed by now.
+    ///
+    /// Call this regularly (until it returns `None`) if no new input arrives, otherwise the latest value might be
+    /// delivered late.
+    pub fn poll(&mut self, now: Instant) -> Option<ControllerEvent> {
+        (0..DATA_PACKAGE_TYPE_COUNT).find_map(|index| {
+            let event = self.states[index].pending?;
+            if self.is_due(index, now) {
+                self.emit(index, event, now);
+                Some(event)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Drop all held back events and forget the last emitted ones, e.g. after the connection has been lost.
+    pub fn reset(&mut self) {
+        for state in &mut self.states {
+            state.last_emitted = None;
+            state.pending = None;
+        }
+    }
+
+    /// Decide what to do with the event, returns it if it should be emitted now.
+    fn process(&mut self, event: ControllerEvent, now: Instant) -> Option<ControllerEvent> {
+        let data_package_type = event.data_package_type();
+        if data_package_type == ControllerDataPackageType::ButtonCommand {
+            return Some(event);
+        }
+
+        let index = data_package_type as usize;
+        if let Some((_, last_event)) = self.states[index].last_emitted {
+            if max_difference(&last_event, &event) < self.settings[index].deadband {
+                // the value is back to (roughly) what has been emitted last => nothing left to emit
+                self.states[index].pending = None;
+                return None;
+            }
+        }
+
+        if self.is_due(index, now) {
+            self.emit(index, event, now);
+            Some(event)
+        } else {
+            self.states[index].pending = Some(event);
+            None
+        }
+    }
+
+    fn is_due(&self, index: usize, now: Instant) -> bool {
+        match self.states[index].last_emitted {
+            Some((emitted_at, _)) => now - emitted_at >= self.settings[index].interval,
+            None => true,
+        }
+    }
+
+    fn emit(&mut self, index: usize, event: ControllerEvent, now: Instant) {
+        self.states[index].last_emitted = Some((now, event));
+        self.states[index].pending = None;
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The largest difference of any value of the two events (which must be of the same type).
+fn max_difference(a: &ControllerEvent, b: &ControllerEvent) -> f32 {
+    let (a, b) = (values(a), values(b));
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| abs_f32(a - b))
+        .fold(0.0, f32::max)
+}
+
+/// All values of the event (padded with zeros), used to compare events of the same type.
+fn values(event: &ControllerEvent) -> [f32; 4] {
+    match event {
+        #[cfg(feature = "button_event")]
+        ControllerEvent::ButtonEvent(_) => [0.0; 4],
+        #[cfg(feature = "color_event")]
+        ControllerEvent::ColorEvent(e) => [e.red() as f32, e.green() as f32, e.blue() as f32, 0.0],
+        #[cfg(feature = "quaternion_event")]
+        ControllerEvent::QuaternionEvent(e) => [e.x(), e.y(), e.z(), e.w()],
+        #[cfg(feature = "accelerometer_event")]
+        ControllerEvent::AccelerometerEvent(e) => [e.x(), e.y(), e.z(), 0.0],
+        #[cfg(feature = "gyro_event")]
+        ControllerEvent::GyroEvent(e) => [e.x(), e.y(), e.z(), 0.0],
+        #[cfg(feature = "magnetometer_event")]
+        ControllerEvent::MagnetometerEvent(e) => [e.x(), e.y(), e.z(), 0.0],
+        #[cfg(feature = "location_event")]
+        ControllerEvent::LocationEvent(e) => [e.latitude(), e.longitude(), e.altitude(), 0.0],
+    }
+}
+
+/// Iterator adaptor which throttles the events, see [`Throttle::filter`].
+#[derive(Debug)]
+pub struct ThrottledEvents<'a, I, C> {
+    events: I,
+    clock: &'a C,
+    throttle: &'a mut Throttle,
+}
+
+impl<I, C> Iterator for ThrottledEvents<'_, I, C>
+where
+    I: Iterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+    C: Clock,
+{
+    type Item = Result<ControllerEvent, ProtocolParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for event in self.events.by_ref() {
+            match event {
+                Ok(event) => {
+                    if let Some(event) = self.throttle.process(event, self.clock.now()) {
+                        return Some(Ok(event));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        self.throttle.poll(self.clock.now()).map(Ok)
+    }
+}
+
+#[cfg(all(test, feature = "button_event", feature = "accelerometer_event"))]
+mod tests {
+    use crate::accelerometer_event::AccelerometerEvent;
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::throttle::Throttle;
+    use crate::time::{Duration, Instant};
+    use crate::{ControllerDataPackageType, ControllerEvent};
+
+    fn accelerometer(x: f32) -> ControllerEvent {
+        ControllerEvent::AccelerometerEvent(AccelerometerEvent::new(x, 0.0, 0.0))
+    }
+
+    fn button(state: ButtonState) -> ControllerEvent {
+        ControllerEvent::ButtonEvent(ButtonEvent::new(Button::Button1, state))
+    }
+
+    fn x_of(event: Option<ControllerEvent>) -> Option<f32> {
+        match event {
+            Some(ControllerEvent::AccelerometerEvent(e)) => Some(e.x()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_no_throttling_by_default() {
+        let mut throttle = Throttle::new();
+        let now = Instant::from_millis(0);
+
+        assert_eq!(x_of(throttle.process(accelerometer(1.0), now)), Some(1.0));
+        assert_eq!(x_of(throttle.process(accelerometer(1.0), now)), Some(1.0));
+    }
+
+    #[test]
+    fn test_interval_latest_wins() {
+        let mut throttle = Throttle::new().with_interval(
+            ControllerDataPackageType::Accelerometer,
+            Duration::from_millis(100),
+        );
+
+        assert_eq!(
+            x_of(throttle.process(accelerometer(1.0), Instant::from_millis(0))),
+            Some(1.0)
+        );
+        assert_eq!(
+            throttle.process(accelerometer(2.0), Instant::from_millis(10)),
+            None
+        );
+        assert_eq!(
+            throttle.process(accelerometer(3.0), Instant::from_millis(20)),
+            None
+        );
+        assert_eq!(throttle.poll(Instant::from_millis(99)), None);
+        assert_eq!(x_of(throttle.poll(Instant::from_millis(100))), Some(3.0));
+        assert_eq!(throttle.poll(Instant::from_millis(300)), None);
+        assert_eq!(
+            x_of(throttle.process(accelerometer(4.0), Instant::from_millis(200))),
+            Some(4.0)
+        );
+    }
+
+    #[test]
+    fn test_deadband() {
+        let mut throttle =
+            Throttle::new().with_deadband(ControllerDataPackageType::Accelerometer, 0.5);
+        let now = Instant::from_millis(0);
+
+        assert_eq!(x_of(throttle.process(accelerometer(1.0), now)), Some(1.0));
+        assert_eq!(throttle.process(accelerometer(1.4), now), None);
+        assert_eq!(throttle.process(accelerometer(0.6), now), None);
+        assert_eq!(x_of(throttle.process(accelerometer(1.5), now)), Some(1.5));
+    }
+
+    #[test]
+    fn test_deadband_discards_pending_event() {
+        let mut throttle = Throttle::new()
+            .with_interval(
+                ControllerDataPackageType::Accelerometer,
+                Duration::from_millis(100),
+            )
+            .with_deadband(ControllerDataPackageType::Accelerometer, 0.5);
+
+        assert!(throttle
+            .process(accelerometer(1.0), Instant::from_millis(0))
+            .is_some());
+        assert_eq!(
+            throttle.process(accelerometer(5.0), Instant::from_millis(10)),
+            None
+        );
+        // back to the emitted value before the interval elapsed => nothing to emit
+        assert_eq!(
+            throttle.process(accelerometer(1.1), Instant::from_millis(20)),
+            None
+        );
+        assert_eq!(throttle.poll(Instant::from_millis(200)), None);
+    }
+
+    #[test]
+    fn test_buttons_always_pass() {
+        let mut throttle = Throttle::new()
+            .with_interval(
+                ControllerDataPackageType::ButtonCommand,
+                Duration::from_secs(1),
+            )
+            .with_deadband(ControllerDataPackageType::ButtonCommand, 1.0);
+        let now = Instant::from_millis(0);
+
+        for state in [
+            ButtonState::Pressed,
+            ButtonState::Released,
+            ButtonState::Pressed,
+            ButtonState::Released,
+        ] {
+            assert_eq!(throttle.process(button(state), now), Some(button(state)));
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut throttle = Throttle::new().with_interval(
+            ControllerDataPackageType::Accelerometer,
+            Duration::from_millis(100),
+        );
+
+        assert!(throttle
+            .process(accelerometer(1.0), Instant::from_millis(0))
+            .is_some());
+        assert_eq!(
+            throttle.process(accelerometer(2.0), Instant::from_millis(10)),
+            None
+        );
+        throttle.reset();
+        assert_eq!(throttle.poll(Instant::from_millis(200)), None);
+        assert!(throttle
+            .process(accelerometer(3.0), Instant::from_millis(210))
+            .is_some());
+    }
+}
@@ -0,0 +1,308 @@
+//! Minimal time handling for the components which need to know when an event has been received.
+//!
+//! The crate does not access any timer itself, instead you have to provide the current time, either directly as an
+//! [`Instant`] or through a [`Clock`]. With the `fugit` feature the types can be converted from & to their
+//! [`fugit`](https://crates.io/crates/fugit) counterparts with 32 or 64 bit ticks (which are e.g. used by RTIC
+//! monotonics). As 32 bit timers wrap around, their instants are converted through an `InstantExtender`.
+
+use core::ops::{Add, Sub};
+
+/// A point in time, stored as microseconds since an arbitrary start (e.g. the boot of the device).
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Instant {
+    micros: u64,
+}
+
+impl Instant {
+    /// Create an instant from the microseconds since the start.
+    pub const fn from_micros(micros: u64) -> Self {
+        Self { micros }
+    }
+
+    /// Create an instant from the milliseconds since the start.
+    pub const fn from_millis(millis: u64) -> Self {
+        Self::from_micros(millis * 1_000)
+    }
+
+    /// The microseconds since the start.
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    /// The time elapsed since the earlier instant, zero if `earlier` is actually later than `self`.
+    pub const fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.micros.saturating_sub(earlier.micros))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        Instant::from_micros(self.micros + rhs.micros)
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Same as [`Instant::saturating_duration_since`].
+    fn sub(self, rhs: Instant) -> Self::Output {
+        self.saturating_duration_since(rhs)
+    }
+}
+
+/// A span of time with microsecond resolution.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Duration {
+    micros: u64,
+}
+
+impl Duration {
+    /// A duration of zero.
+    pub const ZERO: Duration = Duration::from_micros(0);
+
+    /// Create a duration from microseconds.
+    pub const fn from_micros(micros: u64) -> Self {
+        Self { micros }
+    }
+
+    /// Create a duration from milliseconds.
+    pub const fn from_millis(millis: u64) -> Self {
+        Self::from_micros(millis * 1_000)
+    }
+
+    /// Create a duration from seconds.
+    pub const fn from_secs(secs: u64) -> Self {
+        Self::from_micros(secs * 1_000_000)
+    }
+
+    /// The duration in microseconds.
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    /// The duration in milliseconds (rounded down).
+    pub const fn as_millis(&self) -> u64 {
+        self.micros / 1_000
+    }
+
+    /// The duration in seconds.
+    pub fn as_secs_f32(&self) -> f32 {
+        self.micros as f32 / 1_000_000.0
+    }
+}
+
+impl Add<Duration> for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        Duration::from_micros(self.micros + rhs.micros)
+    }
+}
+
+/// Provides the current time.
+///
+/// This is implemented for all closures returning an [`Instant`], so you can e.g. use
+/// `|| Instant::from_micros(timer.now().ticks())`.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> Instant;
+}
+
+impl<F: Fn() -> Instant> Clock for F {
+    fn now(&self) -> Instant {
+        self()
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Instant<u64, NOM, DENOM>> for Instant {
+    fn from(value: fugit::Instant<u64, NOM, DENOM>) -> Self {
+        Instant::from_micros(value.duration_since_epoch().to_micros())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<Instant> for fugit::Instant<u64, 1, 1_000_000> {
+    fn from(value: Instant) -> Self {
+        fugit::Instant::<u64, 1, 1_000_000>::from_ticks(value.micros)
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Duration<u64, NOM, DENOM>> for Duration {
+    fn from(value: fugit::Duration<u64, NOM, DENOM>) -> Self {
+        Duration::from_micros(value.to_micros())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<Duration> for fugit::Duration<u64, 1, 1_000_000> {
+    fn from(value: Duration) -> Self {
+        fugit::Duration::<u64, 1, 1_000_000>::from_ticks(value.micros)
+    }
+}
+
+/// Convert the ticks of a fugit type to microseconds without overflowing.
+#[cfg(feature = "fugit")]
+fn ticks_to_micros<const NOM: u32, const DENOM: u32>(ticks: u64) -> u64 {
+    (u128::from(ticks) * u128::from(NOM) * 1_000_000 / u128::from(DENOM)) as u64
+}
+
+/// Extends the instants of a 32 bit timer, which wrap around, to monotonic [`Instant`]s.
+///
+/// A 32 bit instant can't be converted to an [`Instant`] on its own, as the time would jump backwards at each wrap
+/// around of the timer. The extender remembers the last instant instead and counts the wrap arounds, so it has to be
+/// called at least once per period of the timer (e.g. about 36 hours for a 32 kHz timer) and never with an instant
+/// older than the previous one.
+///
+/// ```
+/// # use adafruit_bluefruit_protocol::time::{Clock, Instant, InstantExtender};
+/// let extender = InstantExtender::<1, 1_000>::new();
+/// let clock = || extender.extend(fugit::Instant::<u32, 1, 1_000>::from_ticks(3));
+/// assert_eq!(clock.now(), Instant::from_millis(3));
+/// ```
+#[cfg(feature = "fugit")]
+#[derive(Debug, Default)]
+pub struct InstantExtender<const NOM: u32, const DENOM: u32> {
+    ticks: core::cell::Cell<u64>,
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> InstantExtender<NOM, DENOM> {
+    /// Create an extender which starts at the first period of the timer.
+    pub const fn new() -> Self {
+        Self {
+            ticks: core::cell::Cell::new(0),
+        }
+    }
+
+    /// Convert the instant of the timer, taking into account the wrap arounds since the start.
+    pub fn extend(&self, instant: fugit::Instant<u32, NOM, DENOM>) -> Instant {
+        let last = self.ticks.get();
+        let ticks = last + u64::from(instant.ticks().wrapping_sub(last as u32));
+        self.ticks.set(ticks);
+        Instant::from_micros(ticks_to_micros::<NOM, DENOM>(ticks))
+    }
+}
+
+/// Like a 32 bit timer the result wraps around, i.e. after about 71 minutes.
+#[cfg(feature = "fugit")]
+impl From<Instant> for fugit::Instant<u32, 1, 1_000_000> {
+    fn from(value: Instant) -> Self {
+        fugit::Instant::<u32, 1, 1_000_000>::from_ticks(value.micros as u32)
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Duration<u32, NOM, DENOM>> for Duration {
+    fn from(value: fugit::Duration<u32, NOM, DENOM>) -> Self {
+        Duration::from_micros(ticks_to_micros::<NOM, DENOM>(u64::from(value.ticks())))
+    }
+}
+
+/// Fails if the duration does not fit into 32 bit microseconds, i.e. if it is longer than about 71 minutes.
+#[cfg(feature = "fugit")]
+impl TryFrom<Duration> for fugit::Duration<u32, 1, 1_000_000> {
+    type Error = core::num::TryFromIntError;
+
+    fn try_from(value: Duration) -> Result<Self, Self::Error> {
+        Ok(fugit::Duration::<u32, 1, 1_000_000>::from_ticks(
+            u32::try_from(value.micros)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::time::{Clock, Duration, Instant};
+
+    #[test]
+    fn test_instant_arithmetic() {
+        let start = Instant::from_millis(10);
+        let end = start + Duration::from_micros(1_500);
+
+        assert_eq!(end.as_micros(), 11_500);
+        assert_eq!(end - start, Duration::from_micros(1_500));
+        assert_eq!(start - end, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_duration_conversion() {
+        let duration = Duration::from_secs(2) + Duration::from_millis(500);
+
+        assert_eq!(duration.as_millis(), 2_500);
+        assert_eq!(duration.as_secs_f32(), 2.5);
+    }
+
+    #[test]
+    fn test_closure_is_clock() {
+        let clock = || Instant::from_micros(42);
+
+        assert_eq!(clock.now(), Instant::from_micros(42));
+    }
+
+    #[test]
+    #[cfg(feature = "fugit")]
+    fn test_fugit_conversion() {
+        let instant: Instant = fugit::Instant::<u64, 1, 1_000>::from_ticks(3).into();
+        assert_eq!(instant, Instant::from_millis(3));
+
+        let duration: Duration = fugit::Duration::<u64, 1, 1_000>::from_ticks(4).into();
+        assert_eq!(duration, Duration::from_millis(4));
+
+        let instant: fugit::Instant<u64, 1, 1_000_000> = Instant::from_micros(5).into();
+        assert_eq!(instant.ticks(), 5);
+    }
+
+    #[test]
+    #[cfg(feature = "fugit")]
+    fn test_fugit_u32_conversion() {
+        use crate::time::InstantExtender;
+
+        let instant = InstantExtender::<1, 1_000>::new()
+            .extend(fugit::Instant::<u32, 1, 1_000>::from_ticks(3));
+        assert_eq!(instant, Instant::from_millis(3));
+
+        let duration: Duration = fugit::Duration::<u32, 1, 1_000>::from_ticks(4).into();
+        assert_eq!(duration, Duration::from_millis(4));
+
+        let instant: fugit::Instant<u32, 1, 1_000_000> = Instant::from_micros(5).into();
+        assert_eq!(instant.ticks(), 5);
+
+        let duration = fugit::Duration::<u32, 1, 1_000_000>::try_from(Duration::from_micros(6));
+        assert_eq!(duration.map(|d| d.ticks()), Ok(6));
+    }
+
+    #[test]
+    #[cfg(feature = "fugit")]
+    fn test_fugit_u32_overflow() {
+        use crate::time::InstantExtender;
+
+        // ticks of a 32 kHz timer shortly before the wrap around, their microseconds do not fit into 32 bits
+        let extender = InstantExtender::<1, 32_768>::new();
+        let instant = extender.extend(fugit::Instant::<u32, 1, 32_768>::from_ticks(u32::MAX));
+        assert_eq!(instant.as_micros(), 131_071_999_969);
+
+        // the instants keep increasing after the wrap around of the timer
+        let instant = extender.extend(fugit::Instant::<u32, 1, 32_768>::from_ticks(1));
+        assert_eq!(instant.as_micros(), 131_072_000_030);
+        let instant = extender.extend(fugit::Instant::<u32, 1, 32_768>::from_ticks(u32::MAX));
+        assert_eq!(instant.as_micros(), 262_143_999_969);
+
+        let instant: fugit::Instant<u32, 1, 1_000_000> =
+            Instant::from_micros(u64::from(u32::MAX) + 7).into();
+        assert_eq!(instant.ticks(), 6);
+
+        assert!(
+            fugit::Duration::<u32, 1, 1_000_000>::try_from(Duration::from_micros(1 << 32)).is_err()
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Implements [`Timestamped`] events and the estimation of the rate at which each event type is received, see [`EventRates`].
+
+use super::time::{Clock, Duration, Instant};
+use super::{
+    abs_f32, ControllerDataPackageType, ControllerEvent, ProtocolParseError,
+    DATA_PACKAGE_TYPE_COUNT,
+};
+
+/// A value together with the time at which it has been received.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Timestamped<T> {
+    value: T,
+    timestamp: Instant,
+}
+
+impl<T> Timestamped<T> {
+    /// Attach the timestamp to the value.
+    pub const fn new(value: T, timestamp: Instant) -> Self {
+        Self { value, timestamp }
+    }
+
+    /// The value.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// The time at which the value has been received.
+    pub fn timestamp(&self) -> Instant {
+        self.timestamp
+    }
+
+    /// Discard the timestamp and return the value.
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+/// Weight of a new gap in the moving averages of [`RateEstimator`].
+const SMOOTHING: f32 = 0.125;
+
+/// Estimates the rate at which an event is received based on the gaps between the events.
+///
+/// The rate and the jitter are exponential moving averages, so they follow changes of the rate after a few events.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RateEstimator {
+    last_seen: Option<Instant>,
+    last_gap: Option<Duration>,
+    /// average gap in seconds
+    mean_gap: f32,
+    /// average deviation from `mean_gap` in seconds
+    jitter: f32,
+}
+
+impl RateEstimator {
+    /// Create an estimator which has not seen any event yet.
+    pub const fn new() -> Self {
+        Self {
+            last_seen: None,
+            last_gap: None,
+            mean_gap: 0.0,
+            jitter: 0.0,
+        }
+    }
+
+    /// Record that an event has been received at the given time.
+    pub fn record(&mut self, now: Instant) {
+        if let Some(last_seen) = self.last_seen {
+            let gap = now - last_seen;
+            let gap_secs = gap.as_secs_f32();
+            if self.last_gap.is_none() {
+                self.mean_gap = gap_secs;
+            } else {
+                let deviation = abs_f32(gap_secs - self.mean_gap);
+                self.mean_gap += SMOOTHING * (gap_secs - self.mean_gap);
+                self.jitter += SMOOTHING * (deviation - self.jitter);
+            }
+            self.last_gap = Some(gap);
+        }
+        self.last_seen = Some(now);
+    }
+
+    /// The estimated rate in Hz, `None` if less than two events have been received.
+    pub fn rate_hz(&self) -> Option<f32> {
+        match self.last_gap {
+            Some(_) if self.mean_gap > 0.0 => Some(1.0 / self.mean_gap),
+            _ => None,
+        }
+    }
+
+    /// The average deviation of the gaps from the average gap, `None` if less than two events have been received.
+    pub fn jitter(&self) -> Option<Duration> {
+        self.last_gap
+            .map(|_| Duration::from_micros((self.jitter * 1_000_000.0) as u64))
+    }
+
+    /// The gap between the last two events, `None` if less than two events have been received.
+    pub fn last_gap(&self) -> Option<Duration> {
+        self.last_gap
+    }
+
+    /// The time at which the last event has been received.
+    pub fn last_seen(&self) -> Option<Instant> {
+        self.last_seen
+    }
+
+    /// Returns `true` if no event has been received for longer than `factor` times the average gap.
+    ///
+    /// As long as the rate is not known (less than two events received) this always returns `false`.
+    pub fn is_stalled(&self, now: Instant, factor: f32) -> bool {
+        match (self.last_seen, self.last_gap) {
+            (Some(last_seen), Some(_)) => (now - last_seen).as_secs_f32() > self.mean_gap * factor,
+            _ => false,
+        }
+    }
+}
+
+/// Keeps a [`RateEstimator`] per [`ControllerDataPackageType`].
+///
+/// ## Example
+/// ```
+/// # #[cfg(feature = "button_event")]
+/// # {
+/// # use adafruit_bluefruit_protocol::time::Instant;
+/// # use adafruit_bluefruit_protocol::timestamp::EventRates;
+/// # use adafruit_bluefruit_protocol::{ControllerDataPackageType, Parser};
+/// # use core::cell::Cell;
+/// let now = Cell::new(Instant::from_millis(0));
+/// let clock = || now.get();
+/// let mut rates = EventRates::new();
+///
+/// for millis in [0, 100, 200] {
+///     now.set(Instant::from_millis(millis));
+///     for event in rates.timestamp(Parser::new(b"!B11:"), &clock) {
+///         assert_eq!(event.unwrap().timestamp(), Instant::from_millis(millis));
+///     }
+/// }
+///
+/// let button_rate = rates.get(ControllerDataPackageType::ButtonCommand);
+/// assert_eq!(button_rate.rate_hz(), Some(10.0));
+/// assert!(button_rate.is_stalled(Instant::from_millis(500), 2.0));
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventRates {
+    estimators: [RateEstimator; DATA_PACKAGE_TYPE_COUNT],
+}
+
+impl EventRates {
+    /// Create the estimators, none of them has seen an event yet.
+    pub const fn new() -> Self {
+        Self {
+            estimators: [RateEstimator::new(); DATA_PACKAGE_TYPE_COUNT],
+        }
+    }
+
+    /// Record that an event has been received at the given time.
+    pub fn record(&mut self, event: &ControllerEvent, now: Instant) {
+        self.estimators[event.data_package_type() as usize].record(now);
+    }
+
+    /// The estimator for the event type.
+    pub fn get(&self, data_package_type: ControllerDataPackageType) -> &RateEstimator {
+        &self.estimators[data_package_type as usize]
+    }
+
+    /// Attach the current time of the clock to each event and record it.
+    ///
+    /// Errors are passed through unchanged.
+    pub fn timestamp<'a, I, C>(
+        &'a mut self,
+        events: I,
+        clock: &'a C,
+    ) -> TimestampedEvents<'a, I::IntoIter, C>
+    where
+        I: IntoIterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+        C: Clock,
+    {
+        TimestampedEvents {
+            events: events.into_iter(),
+            clock,
+            rates: self,
+        }
+    }
+}
+
+/// Iterator adaptor which timestamps the events, see [`EventRates::timestamp`].
+#[derive(Debug)]
+pub struct TimestampedEvents<'a, I, C> {
+    events: I,
+    clock: &'a C,
+    rates: &'a mut EventRates,
+}
+
+impl<I, C> Iterator for TimestampedEvents<'_, I, C>
+where
+    I: Iterator<Item = Result<ControllerEvent, ProtocolParseError>>,
+    C: Clock,
+{
+    type Item = Result<Timestamped<ControllerEvent>, ProtocolParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.events.next()?.map(|event| {
+            let now = self.clock.now();
+            self.rates.record(&event, now);
+            Timestamped::new(event, now)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::time::{Duration, Instant};
+    use crate::timestamp::RateEstimator;
+
+    fn record_all(estimator: &mut RateEstimator, millis: &[u64]) {
+        for m in millis {
+            estimator.record(Instant::from_millis(*m));
+        }
+    }
+
+    #[test]
+    fn test_unknown_rate() {
+        let mut estimator = RateEstimator::new();
+        assert_eq!(estimator.rate_hz(), None);
+
+        estimator.record(Instant::from_millis(10));
+        assert_eq!(estimator.rate_hz(), None);
+        assert_eq!(estimator.jitter(), None);
+        assert_eq!(estimator.last_seen(), Some(Instant::from_millis(10)));
+        assert!(!estimator.is_stalled(Instant::from_millis(100_000), 2.0));
+    }
+
+    #[test]
+    fn test_constant_rate() {
+        let mut estimator = RateEstimator::new();
+        record_all(&mut estimator, &[0, 20, 40, 60, 80]);
+
+        assert_eq!(estimator.rate_hz(), Some(50.0));
+        assert_eq!(estimator.jitter(), Some(Duration::ZERO));
+        assert_eq!(estimator.last_gap(), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut estimator = RateEstimator::new();
+        record_all(&mut estimator, &[0, 20, 30, 50, 60, 80]);
+
+        let rate = estimator.rate_hz().unwrap();
+        assert!(rate > 50.0 && rate < 100.0, "unexpected rate {}", rate);
+        assert!(estimator.jitter().unwrap() > Duration::from_millis(1));
+        assert_eq!(estimator.last_gap(), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_stall() {
+        let mut estimator = RateEstimator::new();
+        record_all(&mut estimator, &[0, 10, 20]);
+
+        assert!(!estimator.is_stalled(Instant::from_millis(35), 2.0));
+        assert!(estimator.is_stalled(Instant::from_millis(45), 2.0));
+    }
+
+    #[cfg(all(feature = "button_event", feature = "color_event"))]
+    #[test]
+    fn test_rates_per_type() {
+        use crate::timestamp::EventRates;
+        use crate::{ControllerDataPackageType, Parser, ProtocolParseError};
+        use core::cell::Cell;
+
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut rates = EventRates::new();
+
+        for millis in [0, 100, 200] {
+            now.set(Instant::from_millis(millis));
+            let mut events = rates.timestamp(Parser::new(b"!B11:!C\xff-96!B13\x00"), &clock);
+            assert!(events.next().unwrap().is_ok());
+            assert!(events.next().unwrap().is_ok());
+            assert_eq!(
+                events.next(),
+                Some(Err(ProtocolParseError::InvalidCrc(0, 0x38)))
+            );
+            assert_eq!(events.next(), None);
+        }
+        now.set(Instant::from_millis(300));
+        assert_eq!(rates.timestamp(Parser::new(b"!B11:"), &clock).count(), 1);
+
+        let buttons = rates.get(ControllerDataPackageType::ButtonCommand);
+        assert_eq!(buttons.rate_hz(), Some(10.0));
+        assert_eq!(buttons.last_seen(), Some(Instant::from_millis(300)));
+        let colors = rates.get(ControllerDataPackageType::Color);
+        assert_eq!(colors.rate_hz(), Some(10.0));
+        assert_eq!(colors.last_seen(), Some(Instant::from_millis(200)));
+        assert_eq!(rates.get(ControllerDataPackageType::Gyro).last_seen(), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Implements the optional validation of the decoded values.
+//!
+//! The 8-bit CRC of the protocol does not detect all transmission errors, so a corrupted message can pass it and
+//! contain arbitrary values. Select a [`Validation`] with `with_validation` on the parsers to reject values which cannot
+//! have been sent by the controller, they are then returned as [`ProtocolParseError::ValidationError`](super::ProtocolParseError::ValidationError).
+//!
+//! ## Example
+//! ```
+//! # use adafruit_bluefruit_protocol::validation::{Validation, ValidationError};
+//! # use adafruit_bluefruit_protocol::{Parser, ProtocolParseError};
+//! # #[cfg(feature = "quaternion_event")]
+//! # {
+//! // a quaternion event with the values x=1, y=-2, z=0, w=0 which is not a rotation
+//! let input = b"!Q\x00\x00\x80?\x00\x00\x00\xc0\x00\x00\x00\x00\x00\x00\x00\x00\x0e";
+//! assert!(Parser::new(input).next().unwrap().is_ok());
+//! assert_eq!(
+//!     Parser::new(input).with_validation(Validation::new()).next().unwrap(),
+//!     Err(ProtocolParseError::ValidationError(ValidationError::QuaternionNotNormalized))
+//! );
+//! # }
+//! ```
+
+use super::{abs_f32, ControllerEvent};
+#[cfg(feature = "fmt")]
+use core::error::Error;
+#[cfg(feature = "fmt")]
+use core::fmt::{Display, Formatter};
+
+/// Errors which are raised if a decoded value is not plausible, see [`Validation`].
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum ValidationError {
+    /// A value is NaN or infinite.
+    NonFiniteValue,
+    /// The latitude of a location event is outside of ±90°.
+    LatitudeOutOfRange,
+    /// The longitude of a location event is outside of ±180°.
+    LongitudeOutOfRange,
+    /// A value of an accelerometer, gyro or magnetometer event exceeds [`Validation::with_max_sensor_value`].
+    SensorValueOutOfRange,
+    /// The norm of a quaternion event differs from 1 by more than [`Validation::with_quaternion_tolerance`].
+    QuaternionNotNormalized,
+}
+
+#[cfg(feature = "fmt")]
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        use ValidationError::*;
+        match self {
+            NonFiniteValue => write!(f, "Value is NaN or infinite"),
+            LatitudeOutOfRange => write!(f, "Latitude is outside of ±90°"),
+            LongitudeOutOfRange => write!(f, "Longitude is outside of ±180°"),
+            SensorValueOutOfRange => write!(f, "Sensor value is out of range"),
+            QuaternionNotNormalized => write!(f, "Quaternion is not normalized"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Error for ValidationError {}
+
+/// The checks applied to the decoded events.
+///
+/// All values must be finite and locations must have a valid latitude & longitude. The tolerance for the norm of
+/// quaternions (`0.01` by default) and the largest absolute value of the other sensors (unlimited by default) can be
+/// configured. Button and color events are always valid.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Validation {
+    quaternion_tolerance: f32,
+    max_sensor_value: f32,
+}
+
+impl Validation {
+    /// Create the validation with the default tolerances.
+    pub const fn new() -> Self {
+        Self {
+            quaternion_tolerance: 0.01,
+            max_sensor_value: f32::MAX,
+        }
+    }
+
+    /// Set by how much the norm of a quaternion may differ from 1.
+    pub const fn with_quaternion_tolerance(mut self, tolerance: f32) -> Self {
+        self.quaternion_tolerance = tolerance;
+        self
+    }
+
+    /// Set the largest absolute value of the accelerometer, gyro and magnetometer events.
+    pub const fn with_max_sensor_value(mut self, max: f32) -> Self {
+        self.max_sensor_value = max;
+        self
+    }
+
+    /// Check whether the values of the event are plausible.
+    pub fn validate(&self, event: &ControllerEvent) -> Result<(), ValidationError> {
+        match event {
+            #[cfg(feature = "button_event")]
+            ControllerEvent::ButtonEvent(_) => Ok(()),
+            #[cfg(feature = "color_event")]
+            ControllerEvent::ColorEvent(_) => Ok(()),
+            #[cfg(feature = "quaternion_event")]
+            ControllerEvent::QuaternionEvent(e) => {
+                self.validate_quaternion(e.x(), e.y(), e.z(), e.w())
+            }
+            #[cfg(feature = "accelerometer_event")]
+            ControllerEvent::AccelerometerEvent(e) => self.validate_sensor(&[e.x(), e.y(), e.z()]),
+            #[cfg(feature = "gyro_event")]
+            ControllerEvent::GyroEvent(e) => self.validate_sensor(&[e.x(), e.y(), e.z()]),
+            #[cfg(feature = "magnetometer_event")]
+            ControllerEvent::MagnetometerEvent(e) => self.validate_sensor(&[e.x(), e.y(), e.z()]),
+            #[cfg(feature = "location_event")]
+            ControllerEvent::LocationEvent(e) => {
+                validate_finite(&[e.latitude(), e.longitude(), e.altitude()])?;
+                if abs_f32(e.latitude()) > 90.0 {
+                    Err(ValidationError::LatitudeOutOfRange)
+                } else if abs_f32(e.longitude()) > 180.0 {
+                    Err(ValidationError::LongitudeOutOfRange)
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    #[allow(unused)] // only used by some of the events
+    fn validate_quaternion(&self, x: f32, y: f32, z: f32, w: f32) -> Result<(), ValidationError> {
+        validate_finite(&[x, y, z, w])?;
+        // compare the squared norm to avoid `sqrt`, which is not available in `core`
+        let squared_norm = x * x + y * y + z * z + w * w;
+        let min = (1.0 - self.quaternion_tolerance).max(0.0);
+        let max = 1.0 + self.quaternion_tolerance;
+        if squared_norm >= min * min && squared_norm <= max * max {
+            Ok(())
+        } else {
+            Err(ValidationError::QuaternionNotNormalized)
+        }
+    }
+
+    #[allow(unused)] // only used by some of the events
+    fn validate_sensor(&self, values: &[f32]) -> Result<(), ValidationError> {
+        validate_finite(values)?;
+        if values.iter().all(|v| abs_f32(*v) <= self.max_sensor_value) {
+            Ok(())
+        } else {
+            Err(ValidationError::SensorValueOutOfRange)
+        }
+    }
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(unused)] // only used by some of the events
+fn validate_finite(values: &[f32]) -> Result<(), ValidationError> {
+    if values.iter().all(|v| v.is_finite()) {
+        Ok(())
+    } else {
+        Err(ValidationError::NonFiniteValue)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "button_event",
+    feature = "gyro_event",
+    feature = "location_event",
+    feature = "quaternion_event"
+))]
+mod tests {
+    use crate::button_event::{Button, ButtonEvent, ButtonState};
+    use crate::gyro_event::GyroEvent;
+    use crate::location_event::LocationEvent;
+    use crate::quaternion_event::QuaternionEvent;
+    use crate::validation::{Validation, ValidationError};
+    use crate::{ControllerEvent, Parser, ProtocolParseError};
+
+    #[test]
+    fn test_valid_events() {
+        let validation = Validation::new();
+        for event in [
+            ControllerEvent::ButtonEvent(ButtonEvent::new(Button::Up, ButtonState::Pressed)),
+            ControllerEvent::QuaternionEvent(QuaternionEvent::new(0.0, 0.0, 0.6, 0.8)),
+            ControllerEvent::GyroEvent(GyroEvent::new(1e30, -1e30, 0.0)),
+            ControllerEvent::LocationEvent(LocationEvent::new(-90.0, 180.0, -400.0)),
+        ] {
+            assert_eq!(validation.validate(&event), Ok(()), "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn test_invalid_events() {
+        let validation = Validation::new().with_max_sensor_value(100.0);
+        for (event, error) in [
+            (
+                ControllerEvent::GyroEvent(GyroEvent::new(1.0, f32::NAN, 0.0)),
+                ValidationError::NonFiniteValue,
+            ),
+            (
+                ControllerEvent::GyroEvent(GyroEvent::new(1.0, -100.5, 0.0)),
+                ValidationError::SensorValueOutOfRange,
+            ),
+            (
+                ControllerEvent::QuaternionEvent(QuaternionEvent::new(
+                    0.0,
+                    0.0,
+                    0.0,
+                    f32::INFINITY,
+                )),
+                ValidationError::NonFiniteValue,
+            ),
+            (
+                ControllerEvent::QuaternionEvent(QuaternionEvent::new(0.0, 0.0, 0.0, 1.02)),
+                ValidationError::QuaternionNotNormalized,
+            ),
+            (
+                ControllerEvent::QuaternionEvent(QuaternionEvent::new(0.0, 0.0, 0.0, 0.0)),
+                ValidationError::QuaternionNotNormalized,
+            ),
+            (
+                ControllerEvent::LocationEvent(LocationEvent::new(90.5, 0.0, 0.0)),
+                ValidationError::LatitudeOutOfRange,
+            ),
+            (
+                ControllerEvent::LocationEvent(LocationEvent::new(0.0, -180.5, 0.0)),
+                ValidationError::LongitudeOutOfRange,
+            ),
+            (
+                ControllerEvent::LocationEvent(LocationEvent::new(0.0, 0.0, f32::NEG_INFINITY)),
+                ValidationError::NonFiniteValue,
+            ),
+        ] {
+            assert_eq!(validation.validate(&event), Err(error), "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn test_quaternion_tolerance() {
+        let event = ControllerEvent::QuaternionEvent(QuaternionEvent::new(0.0, 0.0, 0.0, 1.02));
+        assert_eq!(
+            Validation::new()
+                .with_quaternion_tolerance(0.05)
+                .validate(&event),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_parser_skips_invalid_event() {
+        let invalid = ControllerEvent::LocationEvent(LocationEvent::new(f32::NAN, 0.0, 0.0));
+        let valid =
+            ControllerEvent::ButtonEvent(ButtonEvent::new(Button::Up, ButtonState::Pressed));
+        let mut input = [0u8; 20];
+        input[..15].copy_from_slice(invalid.to_frame().as_bytes());
+        input[15..].copy_from_slice(valid.to_frame().as_bytes());
+
+        let mut parser = Parser::new(&input).with_validation(Validation::new());
+        assert_eq!(
+            parser.next(),
+            Some(Err(ProtocolParseError::ValidationError(
+                ValidationError::NonFiniteValue
+            )))
+        );
+        assert_eq!(parser.next(), Some(Ok(valid)));
+        assert_eq!(parser.next(), None);
+    }
+}